@@ -1,5 +1,6 @@
-use crate::{PartType, SchematicCell};
+use crate::{PartNumber, PartType, SchematicCell};
 use colored::*;
+use std::{cell::RefCell, collections::HashMap, fmt::Write, rc::Rc};
 
 // print schematic
 pub fn print_schematic(schematic: &Vec<Vec<SchematicCell>>) {
@@ -24,3 +25,64 @@ pub fn print_schematic(schematic: &Vec<Vec<SchematicCell>>) {
         println!();
     });
 }
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// export the part <-> part number adjacency graph in graphviz dot format
+pub fn schematic_to_dot(schematic: &[Vec<SchematicCell>]) -> String {
+    let mut dot = String::from("graph schematic {\n");
+    let mut number_ids: HashMap<*const RefCell<PartNumber>, String> = HashMap::new();
+
+    // one node per part number, named after the cell of its first digit
+    for (y, row) in schematic.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if let SchematicCell::PartialPartNumber(_, part_number) = cell {
+                if number_ids.contains_key(&Rc::as_ptr(part_number)) {
+                    continue;
+                }
+
+                let id = format!("n_{}_{}", y, x);
+                let value = part_number.borrow().value;
+                let is_part = part_number.borrow().part.is_some();
+                writeln!(
+                    dot,
+                    "    {} [shape=box, label=\"{}\", value={}, part_number={}, pos=\"{},{}!\"];",
+                    id, value, value, is_part, x, -(y as i64)
+                )
+                .unwrap();
+                number_ids.insert(Rc::as_ptr(part_number), id);
+            }
+        }
+    }
+
+    // one node per part and an edge to every adjacent part number
+    for (y, row) in schematic.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if let SchematicCell::Part(part) = cell {
+                let part = part.borrow();
+                let (symbol, gear) = match part.part_type {
+                    PartType::Gear => ('*', true),
+                    PartType::Unknown(c) => (c, false),
+                };
+
+                let id = format!("p_{}_{}", y, x);
+                let symbol = escape_dot(&symbol.to_string());
+                writeln!(
+                    dot,
+                    "    {} [shape=circle, label=\"{}\", symbol=\"{}\", gear={}, pos=\"{},{}!\"];",
+                    id, symbol, symbol, gear, x, -(y as i64)
+                )
+                .unwrap();
+
+                for part_number in &part.part_numbers {
+                    writeln!(dot, "    {} -- {};", id, number_ids[&Rc::as_ptr(part_number)]).unwrap();
+                }
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
@@ -1,4 +1,4 @@
-use std::{rc::Rc, cell::RefCell};
+use std::{rc::Rc, cell::RefCell, io::BufRead};
 
 mod debug;
 
@@ -178,6 +178,16 @@ fn calc_partnumber_sum(input: &str) -> u32 {
     }).sum()
 }
 
+// export the fully resolved schematic of the input as a graphviz dot graph
+fn export_schematic_dot(input: &str) -> String {
+    let mut schematic = parse_schematic(input);
+    transform_part_numbers(&mut schematic);
+    assign_part_numbers(&mut schematic);
+    transform_to_geared_parts(&mut schematic);
+
+    debug::schematic_to_dot(&schematic)
+}
+
 fn get_all_gear_parts(schematic: &Schematic) -> Vec<Rc<RefCell<Part>>> {
     let mut gear_parts = Vec::new();
     for y in 0..schematic.len() {
@@ -212,6 +222,83 @@ fn calc_gear_ratio_sum(input: &str) -> u32 {
     }).sum()
 }
 
+fn is_symbol(c: char) -> bool {
+    c != '.' && !c.is_ascii_digit()
+}
+
+// collect all distinct numbers in row which touch one of the columns x - 1..=x + 1
+fn adjacent_numbers(row: &[char], x: usize, numbers: &mut Vec<u32>) {
+    let mut nx = x.saturating_sub(1);
+    while nx <= x + 1 && nx < row.len() {
+        if !row[nx].is_ascii_digit() {
+            nx += 1;
+            continue;
+        }
+
+        // walk back to the first digit of the number, then read it to its end
+        let mut start = nx;
+        while start > 0 && row[start - 1].is_ascii_digit() {
+            start -= 1;
+        }
+        let mut end = start;
+        let mut value = 0;
+        while end < row.len() && row[end].is_ascii_digit() {
+            value = value * 10 + row[end].to_digit(10).unwrap();
+            end += 1;
+        }
+
+        numbers.push(value);
+        nx = end;
+    }
+}
+
+// add the part numbers and gear ratio of all parts in current to the sums
+fn process_row(
+    previous: Option<&[char]>,
+    current: &[char],
+    next: Option<&[char]>,
+    sums: &mut (u32, u32),
+) {
+    let mut numbers = Vec::new();
+    for (x, c) in current.iter().enumerate() {
+        if !is_symbol(*c) {
+            continue;
+        }
+
+        numbers.clear();
+        for row in [previous, Some(current), next].into_iter().flatten() {
+            adjacent_numbers(row, x, &mut numbers);
+        }
+
+        sums.0 += numbers.iter().sum::<u32>();
+        if *c == '*' && numbers.len() == 2 {
+            sums.1 += numbers[0] * numbers[1];
+        }
+    }
+}
+
+// calculates (part number sum, gear ratio sum) while only keeping three rows of the schematic in memory
+fn calc_sums_streaming<R: BufRead>(reader: R) -> std::io::Result<(u32, u32)> {
+    let mut sums = (0, 0);
+    let mut previous: Option<Vec<char>> = None;
+    let mut current: Option<Vec<char>> = None;
+
+    for line in reader.lines() {
+        let next = line?.chars().collect::<Vec<char>>();
+        if let Some(current) = &current {
+            process_row(previous.as_deref(), current, Some(&next), &mut sums);
+        }
+        previous = current;
+        current = Some(next);
+    }
+
+    if let Some(current) = &current {
+        process_row(previous.as_deref(), current, None, &mut sums);
+    }
+
+    Ok(sums)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -244,4 +331,34 @@ mod tests {
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
         assert_eq!(calc_gear_ratio_sum(&file), 93994191)
     }
+
+    #[test]
+    fn small_input_dot_export() {
+        let input = include_str!("../input/small.txt");
+        let dot = export_schematic_dot(input);
+
+        assert!(dot.starts_with("graph schematic {\n"));
+        assert_eq!(dot.matches(" -- ").count(), 8);
+        assert_eq!(dot.matches("shape=box").count(), 10);
+        assert_eq!(dot.matches("shape=circle").count(), 6);
+        assert_eq!(dot.matches("gear=true").count(), 2);
+        assert!(dot.contains("n_0_0 [shape=box, label=\"467\", value=467, part_number=true"));
+        assert!(dot.contains("n_0_5 [shape=box, label=\"114\", value=114, part_number=false"));
+        assert!(dot.contains("p_1_3 -- n_0_0;"));
+        assert!(dot.contains("p_1_3 -- n_2_2;"));
+    }
+
+    #[test]
+    fn small_input_streaming() {
+        let input = include_str!("../input/small.txt");
+        assert_eq!(calc_sums_streaming(input.as_bytes()).unwrap(), (4361, 467835))
+    }
+
+    #[test]
+    fn large_input_streaming() {
+        // Only three rows of the file are held in memory at any time
+        let file = std::fs::File::open("input/big.txt").expect("Could not open input file");
+        let reader = std::io::BufReader::new(file);
+        assert_eq!(calc_sums_streaming(reader).unwrap(), (553825, 93994191))
+    }
 }
@@ -1,65 +1,238 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Result};
+use nom::{
+    bytes::complete::tag,
+    character::complete::{char, space1, u32 as parse_u32},
+    combinator::{all_consuming, map},
+    multi::separated_list1,
+    sequence::{delimited, pair, separated_pair, tuple},
+    IResult,
+};
+
 mod debug;
 
-fn parse_card(input: &str) -> (Vec<u32>, Vec<u32>) {
-    input
-        .split_once(':')
-        .map(|(card, numbers)| numbers)
-        .unwrap()
-        .split_once('|')
-        .map(|(winning_numbers, numbers_you_have)| {
-            (
-                winning_numbers
-                    .split_whitespace()
-                    .map(|n| n.trim().parse::<u32>().unwrap())
-                    .collect::<Vec<u32>>(),
-                numbers_you_have
-                    .split_whitespace()
-                    .map(|n| n.trim().parse::<u32>().unwrap())
-                    .collect::<Vec<u32>>(),
-            )
-        })
-        .unwrap()
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Scratchcard {
+    id: u32,
+    winning: Vec<u32>,
+    have: Vec<u32>,
 }
 
-fn score_card(number_of_matching_cards: u32) -> u32 {
-    if number_of_matching_cards == 0 {
-        return 0;
+impl Scratchcard {
+    // Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+    fn parse(input: &str) -> IResult<&str, Self> {
+        let parse_id = delimited(pair(tag("Card"), space1), parse_u32, pair(char(':'), space1));
+        let parse_numbers = || separated_list1(space1, parse_u32);
+        let parse_sides = separated_pair(
+            parse_numbers(),
+            tuple((space1, char('|'), space1)),
+            parse_numbers(),
+        );
+
+        map(pair(parse_id, parse_sides), |(id, (winning, have))| {
+            Scratchcard { id, winning, have }
+        })(input)
+    }
+
+    fn matching_numbers(&self) -> u32 {
+        let have = self.have.iter().collect::<HashSet<_>>();
+        self.winning.iter().filter(|n| have.contains(n)).count() as u32
     }
 
-    1 * 2u32.pow(number_of_matching_cards - 1)
+    // numbers which occur more than once on the winning or on the have side
+    fn duplicate_numbers(&self) -> (Vec<u32>, Vec<u32>) {
+        fn duplicates(numbers: &[u32]) -> Vec<u32> {
+            let mut seen = HashSet::new();
+            let mut duplicates = Vec::new();
+            for n in numbers {
+                if !seen.insert(n) && !duplicates.contains(n) {
+                    duplicates.push(*n);
+                }
+            }
+            duplicates
+        }
+
+        (duplicates(&self.winning), duplicates(&self.have))
+    }
 }
 
-fn number_of_matching_numbers(winning_numbers: &[u32], numbers_you_have: &[u32]) -> u32 {
-    winning_numbers
-        .iter()
-        .filter(|n| numbers_you_have.contains(n))
-        .count() as u32
+// parse all cards and make sure that no side contains a number twice and that the card ids are contiguous
+fn parse_scratchcards(input: &str) -> Result<Vec<Scratchcard>> {
+    let mut cards: Vec<Scratchcard> = Vec::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let (_, card) = all_consuming(Scratchcard::parse)(line)
+            .map_err(|e| anyhow!("line {}: could not parse card: {}", i + 1, e))?;
+
+        let (winning_duplicates, have_duplicates) = card.duplicate_numbers();
+        if !winning_duplicates.is_empty() {
+            bail!("card {}: duplicate winning numbers {:?}", card.id, winning_duplicates);
+        }
+        if !have_duplicates.is_empty() {
+            bail!("card {}: duplicate numbers you have {:?}", card.id, have_duplicates);
+        }
+
+        if let Some(previous) = cards.last() {
+            if card.id != previous.id + 1 {
+                bail!("card {} follows card {}, card ids must be contiguous", card.id, previous.id);
+            }
+        }
+
+        cards.push(card);
+    }
+
+    Ok(cards)
 }
 
-fn calc_score_of_all_cards(input: &str) -> u32 {
-    input.lines().map(|line| {
-        let (winning_numbers, numbers_you_have) = parse_card(line);
-        let score = score_card(number_of_matching_numbers(&winning_numbers, &numbers_you_have));
-        println!("{} => {}", line, score);
-        score 
-    }).sum()
+// what happens to copies won by a card near the end of the table which would land past the last card
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum CopyOverflow {
+    // copies past the last card are dropped
+    Clamp,
+    // copies past the last card are an error
+    Error,
+    // copies past the last card continue at the first card. The table is still processed in a single
+    // pass, so copies added to an already processed card do not win any further copies.
+    Wrap,
 }
 
-fn calc_accumulated_score_of_all_cards(input: &str) -> u32 {
-    // create an array num_cards of length input.lines().count() with all values set to 1
-    let mut num_cards = vec![1; input.lines().count()];
+// number of instances of each card (originals plus won copies)
+fn calc_card_copies(cards: &[Scratchcard], overflow: CopyOverflow) -> Result<Vec<u64>> {
+    // create an array num_cards of length cards.len() with all values set to 1
+    let mut num_cards = vec![1u64; cards.len()];
+
+    for (i, card) in cards.iter().enumerate() {
+        for j in 0..card.matching_numbers() as usize {
+            let target = match (i + j + 1, overflow) {
+                (target, _) if target < cards.len() => target,
+                (_, CopyOverflow::Clamp) => break,
+                (target, CopyOverflow::Error) => bail!(
+                    "card {} wins a copy of card {}, but the table ends at card {}",
+                    card.id,
+                    card.id as usize + target - i,
+                    cards[cards.len() - 1].id
+                ),
+                (target, CopyOverflow::Wrap) => target % cards.len(),
+            };
 
-    input.lines().enumerate().for_each(|(i, line)| {
-        let (winning_numbers, numbers_you_have) = parse_card(line);
-        let number_of_matching_numbers = number_of_matching_numbers(&winning_numbers, &numbers_you_have);
-        for j in 0..number_of_matching_numbers as usize {
-            num_cards[i + j + 1] += num_cards[i];
+            num_cards[target] = num_cards[target]
+                .checked_add(num_cards[i])
+                .ok_or_else(|| anyhow!("number of copies of card {} overflows", cards[target].id))?;
         }
-    });
+    }
+
+    Ok(num_cards)
+}
+
+// a rule which turns a table of cards into one score per card
+trait ScoringRule {
+    fn name(&self) -> &str;
+
+    fn score_cards(&self, cards: &[Scratchcard]) -> Result<Vec<u64>>;
+
+    fn score(&self, cards: &[Scratchcard]) -> Result<u64> {
+        self.score_cards(cards)?
+            .iter()
+            .try_fold(0u64, |acc, score| acc.checked_add(*score))
+            .ok_or_else(|| anyhow!("total score overflows with rule {}", self.name()))
+    }
+}
+
+// score each card on its own from its number of matching numbers
+fn score_each_card(cards: &[Scratchcard], score: impl Fn(u32) -> Option<u64>) -> Result<Vec<u64>> {
+    cards
+        .iter()
+        .map(|card| {
+            score(card.matching_numbers())
+                .ok_or_else(|| anyhow!("score of card {} overflows", card.id))
+        })
+        .collect()
+}
+
+// 1 point for the first match, doubled for every further match
+struct Doubling;
+
+impl ScoringRule for Doubling {
+    fn name(&self) -> &str {
+        "doubling"
+    }
+
+    fn score_cards(&self, cards: &[Scratchcard]) -> Result<Vec<u64>> {
+        score_each_card(cards, |matches| match matches {
+            0 => Some(0),
+            n => 2u64.checked_pow(n - 1),
+        })
+    }
+}
+
+// 1 point per match
+struct Linear;
+
+impl ScoringRule for Linear {
+    fn name(&self) -> &str {
+        "linear"
+    }
+
+    fn score_cards(&self, cards: &[Scratchcard]) -> Result<Vec<u64>> {
+        score_each_card(cards, |matches| Some(matches as u64))
+    }
+}
+
+// the n-th fibonacci number for n matches (1, 1, 2, 3, 5, ...)
+struct Fibonacci;
+
+impl ScoringRule for Fibonacci {
+    fn name(&self) -> &str {
+        "fibonacci"
+    }
 
-    println!("{:?}", num_cards);
+    fn score_cards(&self, cards: &[Scratchcard]) -> Result<Vec<u64>> {
+        score_each_card(cards, |matches| {
+            let (mut a, mut b) = (0u64, 1u64);
+            for _ in 0..matches {
+                (a, b) = (b, a.checked_add(b)?);
+            }
+            Some(a)
+        })
+    }
+}
+
+// every card scores the number of its instances after winning copies of the following cards
+struct CopyCascade {
+    overflow: CopyOverflow,
+}
+
+impl ScoringRule for CopyCascade {
+    fn name(&self) -> &str {
+        "copy cascade"
+    }
+
+    fn score_cards(&self, cards: &[Scratchcard]) -> Result<Vec<u64>> {
+        calc_card_copies(cards, self.overflow)
+    }
+}
+
+// score the same table with every rule, e.g. to compare tournament variants
+fn score_with_rules(input: &str, rules: &[&dyn ScoringRule]) -> Result<Vec<(String, u64)>> {
+    let cards = parse_scratchcards(input)?;
+
+    rules
+        .iter()
+        .map(|rule| Ok((rule.name().to_string(), rule.score(&cards)?)))
+        .collect()
+}
+
+fn calc_score_of_all_cards(input: &str) -> u64 {
+    let cards = parse_scratchcards(input).unwrap();
+
+    Doubling.score(&cards).unwrap()
+}
+
+fn calc_accumulated_score_of_all_cards(input: &str) -> u64 {
+    let cards = parse_scratchcards(input).unwrap();
 
-    num_cards.iter().sum()
+    CopyCascade { overflow: CopyOverflow::Error }.score(&cards).unwrap()
 }
 
 #[cfg(test)]
@@ -67,6 +240,86 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_scratchcard_parse() {
+        let input = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53";
+        let expected = Scratchcard {
+            id: 1,
+            winning: vec![41, 48, 83, 86, 17],
+            have: vec![83, 86, 6, 31, 17, 9, 48, 53],
+        };
+        let result = Scratchcard::parse(input).unwrap().1;
+        assert_eq!(result, expected);
+        assert_eq!(result.matching_numbers(), 4);
+    }
+
+    #[test]
+    fn test_parse_scratchcards_duplicates() {
+        let card = Scratchcard::parse("Card 7: 1 2 1 | 3 3 4 3").unwrap().1;
+        assert_eq!(card.duplicate_numbers(), (vec![1], vec![3]));
+
+        let error = parse_scratchcards("Card 1: 1 2 | 3 4\nCard 2: 5 6 | 7 7").unwrap_err();
+        assert_eq!(error.to_string(), "card 2: duplicate numbers you have [7]");
+    }
+
+    #[test]
+    fn test_parse_scratchcards_contiguous_ids() {
+        assert!(parse_scratchcards("Card 1: 1 | 2\nCard 2: 3 | 4").is_ok());
+
+        let error = parse_scratchcards("Card 1: 1 | 2\nCard 3: 3 | 4").unwrap_err();
+        assert_eq!(error.to_string(), "card 3 follows card 1, card ids must be contiguous");
+
+        let error = parse_scratchcards("Card 1: 1 | 2\nCard 2 3 | 4").unwrap_err();
+        assert!(error.to_string().starts_with("line 2: could not parse card"));
+    }
+
+    #[test]
+    fn test_card_copies() {
+        let cards = parse_scratchcards(include_str!("../input/small.txt")).unwrap();
+        let expected = vec![1, 2, 4, 8, 14, 1];
+        assert_eq!(calc_card_copies(&cards, CopyOverflow::Error).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_card_copies_overflow() {
+        // card 2 wins two copies, but only card 3 is left
+        let input = "Card 1: 1 | 2\nCard 2: 1 2 | 1 2\nCard 3: 5 | 1";
+        let cards = parse_scratchcards(input).unwrap();
+
+        assert_eq!(calc_card_copies(&cards, CopyOverflow::Clamp).unwrap(), vec![1, 1, 2]);
+        assert_eq!(calc_card_copies(&cards, CopyOverflow::Wrap).unwrap(), vec![2, 1, 2]);
+
+        let error = calc_card_copies(&cards, CopyOverflow::Error).unwrap_err();
+        assert_eq!(error.to_string(), "card 2 wins a copy of card 4, but the table ends at card 3");
+    }
+
+    #[test]
+    fn test_scoring_rules() {
+        let cards = parse_scratchcards(include_str!("../input/small.txt")).unwrap();
+
+        assert_eq!(Doubling.score_cards(&cards).unwrap(), vec![8, 2, 2, 1, 0, 0]);
+        assert_eq!(Linear.score_cards(&cards).unwrap(), vec![4, 2, 2, 1, 0, 0]);
+        assert_eq!(Fibonacci.score_cards(&cards).unwrap(), vec![3, 1, 1, 1, 0, 0]);
+
+        let cascade = CopyCascade { overflow: CopyOverflow::Clamp };
+        assert_eq!(cascade.score_cards(&cards).unwrap(), vec![1, 2, 4, 8, 14, 1]);
+    }
+
+    #[test]
+    fn test_score_with_rules() {
+        let input = include_str!("../input/small.txt");
+        let cascade = CopyCascade { overflow: CopyOverflow::Error };
+        let rules: [&dyn ScoringRule; 4] = [&Doubling, &Linear, &Fibonacci, &cascade];
+
+        let expected = vec![
+            ("doubling".to_string(), 13),
+            ("linear".to_string(), 9),
+            ("fibonacci".to_string(), 6),
+            ("copy cascade".to_string(), 30),
+        ];
+        assert_eq!(score_with_rules(input, &rules).unwrap(), expected);
+    }
+
     #[test]
     fn small_input() {
         // The easiest way to open the data is to include it into the generated binary.
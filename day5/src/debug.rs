@@ -1 +1,78 @@
-use colored::*;
\ No newline at end of file
+use crate::{MappingTrace, NumberRange};
+use colored::*;
+use serde_json::{json, Value};
+
+// render a trace as a table with one row per applied map
+pub fn format_trace_table(trace: &MappingTrace) -> String {
+    let header = ["category", "input", "map", "output"].map(String::from);
+    let rows = trace
+        .steps
+        .iter()
+        .map(|step| {
+            [
+                format!("{} -> {}", step.source, step.destination),
+                step.input.to_string(),
+                step.entry
+                    .map_or("identity".to_string(), |(entry, number_map)| {
+                        format!("entry {}: {:?}", entry + 1, number_map)
+                    }),
+                step.output.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    let format_row = |row: &[String; 4]| {
+        row.iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+
+    let mut table = format_row(&header) + "\n";
+    table += &widths.map(|width| "-".repeat(width)).join("-+-");
+    table += "\n";
+    for row in &rows {
+        table += &format_row(row);
+        table += "\n";
+    }
+    table
+}
+
+fn range_to_json(range: &NumberRange) -> Value {
+    json!({ "start": range.start, "end": range.end })
+}
+
+pub fn trace_to_json(trace: &MappingTrace) -> Value {
+    let steps = trace
+        .steps
+        .iter()
+        .map(|step| {
+            json!({
+                "source": step.source,
+                "destination": step.destination,
+                "input": step.input,
+                // entries are counted from 1, like in the lint messages
+                "entry": step.entry.map(|(entry, _)| entry + 1),
+                "map": step.entry.map(|(_, number_map)| json!({
+                    "src": range_to_json(&number_map.src),
+                    "dest": range_to_json(&number_map.dest),
+                })),
+                "output": step.output,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "input": trace.input,
+        "output": trace.output,
+        "steps": steps,
+    })
+}
@@ -1,63 +1,44 @@
+use advent_of_code_intervals::{Interval, IntervalSet};
+use anyhow::{anyhow, bail, Result};
 use env_logger;
 use log;
 use nom::{
     bytes::complete::tag,
-    character::complete::{alphanumeric1, char, line_ending, space1, u64 as parse_u64},
+    character::complete::{alphanumeric1, line_ending, multispace0, space1, u64 as parse_u64},
     combinator::map,
     multi::separated_list1,
     sequence::{pair, preceded, separated_pair, terminated},
-    IResult,
+    IResult, Parser,
 };
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+    ParserExt,
+};
+use std::str::FromStr;
 use std::fmt::Formatter;
 use std::fmt;
 use std::fmt::Debug;
 mod debug;
 
-#[derive(PartialEq, Clone, Copy)]
-struct NumberRange {
-    start: u64,
-    end: u64,
-}
+type NumberRange = Interval;
 
-impl NumberRange {
-    fn contains(&self, number: &u64) -> bool {
-        *number >= self.start && *number <= self.end
-    }
+type ParseResult<'a, T> = IResult<&'a str, T, ErrorTree<&'a str>>;
 
-    fn overlap(&self, other: &NumberRange) -> Option<NumberRange> {
-        if self.start <= other.end && self.end >= other.start {
-            Some(NumberRange {
-                start: self.start.max(other.start),
-                end: self.end.min(other.end),
-            })
-        } else {
-            None
-        }
+// range of length numbers beginning at start, rejecting empty ranges and ranges beyond u64::MAX
+fn number_range_with_length(start: u64, length: u64) -> Result<NumberRange> {
+    if length == 0 {
+        bail!("range starting at {} has length 0", start);
     }
 
-    fn difference(&self, other: &NumberRange) -> Vec<NumberRange> {
-        let mut difference = Vec::new();
-        if self.start < other.start {
-            difference.push(NumberRange {
-                start: self.start,
-                end: self.end.min(other.start - 1),
-            });
-        }
-        if self.end > other.end {
-            difference.push(NumberRange {
-                start: self.start.max(other.end + 1),
-                end: self.end,
-            });
-        }
-        difference
-    }
+    NumberRange::with_length(start, length)
+        .ok_or_else(|| {
+            anyhow!("range starting at {} with length {} exceeds {}", start, length, u64::MAX)
+        })
 }
 
-impl Debug for NumberRange {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "({}; {})", self.start, self.end)
-    }
-}
+const SEED: &str = "seed";
+const LOCATION: &str = "location";
 
 #[derive(PartialEq, Clone, Copy)]
 struct NumberMap {
@@ -66,25 +47,26 @@ struct NumberMap {
 }
 
 impl NumberMap {
-    fn parse(input: &str) -> IResult<&str, Self> {
+    fn new(dest_start: u64, src_start: u64, length: u64) -> Result<Self> {
+        Ok(NumberMap {
+            src: number_range_with_length(src_start, length)?,
+            dest: number_range_with_length(dest_start, length)?,
+        })
+    }
+
+    fn parse(input: &str) -> ParseResult<'_, Self> {
         let parse_three_numbers = separated_pair(
             separated_pair(parse_u64, space1, parse_u64),
             space1,
             parse_u64,
         );
 
-        map(parse_three_numbers, |((dest_start, src_start), length)| {
-            NumberMap {
-                src: NumberRange {
-                    start: src_start,
-                    end: src_start + length - 1,
-                },
-                dest: NumberRange {
-                    start: dest_start,
-                    end: dest_start + length - 1,
-                },
-            }
-        })(input)
+        // an invalid range is a hard error instead of just ending the list of ranges
+        parse_three_numbers
+            .map_res_cut(|((dest_start, src_start), length)| {
+                NumberMap::new(dest_start, src_start, length)
+            })
+            .parse(input)
     }
 
     fn map(&self, number: &u64) -> Option<u64> {
@@ -136,6 +118,19 @@ impl NumberMap {
         }
     }
 
+    // the part of this map which maps the numbers in src_range (which has to lie within src)
+    fn restrict(&self, src_range: &NumberRange) -> NumberMap {
+        NumberMap {
+            src: *src_range,
+            dest: self.map_range(src_range).unwrap(),
+        }
+    }
+
+    // offset added by this map, i.e. dest - src
+    fn offset(&self) -> i128 {
+        self.dest.start as i128 - self.src.start as i128
+    }
+
     fn split_by_non_chainable_dest(before: &NumberMap, after: &NumberMap) -> Vec<NumberMap> {
         before
             .dest
@@ -154,64 +149,217 @@ impl Debug for NumberMap {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 struct MultiMap {
+    source: String,
+    destination: String,
     number_maps: Vec<NumberMap>,
+    // the maps as they were given, before normalisation
+    entries: Vec<NumberMap>,
+    // sorted source ranges which are mapped by an entry (by its index), after resolving overlaps by the first match
+    origins: Vec<(NumberRange, usize)>,
+    // source ranges of the unnormalised maps which overlap each other
+    overlaps: Vec<Overlap>,
+}
+
+// the entries, origins and overlaps are a leftover of parsing and do not change what the map does
+impl PartialEq for MultiMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+            && self.destination == other.destination
+            && self.number_maps == other.number_maps
+    }
+}
+
+// seed-to-soil map:
+// 52 50 48
+// 50 98 2
+impl fmt::Display for MultiMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} map:", self.name())?;
+
+        // identity segments are implicit
+        let mut number_maps = self
+            .number_maps
+            .iter()
+            .filter(|number_map| number_map.offset() != 0)
+            .peekable();
+
+        // the format needs at least one entry, an identity entry keeps the map unchanged
+        if number_maps.peek().is_none() {
+            return write!(f, "\n0 0 1");
+        }
+
+        for number_map in number_maps {
+            write!(
+                f,
+                "\n{} {} {}",
+                number_map.dest.start,
+                number_map.src.start,
+                number_map.src.end - number_map.src.start + 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// two entries of a map (by their index) whose source ranges overlap, the first one wins for the shared numbers
+#[derive(Debug, PartialEq, Clone)]
+struct Overlap {
+    first: usize,
+    second: usize,
+    src: NumberRange,
 }
 
 impl MultiMap {
     // seed-to-soil map:
     // 50 98 2
     // 52 50 48
-    fn parse(input: &str) -> IResult<&str, Self> {
-        // parse title (i.e. "seed-to-soil map:")
-        let parse_title_name = map(
-            separated_pair(
-                separated_pair(alphanumeric1, char('-'), alphanumeric1),
-                char('-'),
-                alphanumeric1,
-            ),
-            |((a, b), c)| format!("{a}-{b}-{c}"),
-        );
-        let parse_title = pair(parse_title_name, tag(" map:"));
+    fn parse(input: &str) -> ParseResult<'_, Self> {
+        // parse title (i.e. "seed-to-soil map:") into source and destination category
+        let parse_title_name = separated_pair(alphanumeric1, tag("-to-"), alphanumeric1);
+        let parse_title = terminated(parse_title_name, tag(" map:"));
 
         // parse list of ranges
         let parse_ranges = separated_list1(line_ending, NumberMap::parse);
 
-        let parse_map = preceded(terminated(parse_title, line_ending), parse_ranges);
+        let parse_map = separated_pair(parse_title, line_ending, parse_ranges);
 
-        map(parse_map, |ranges| MultiMap {
-            number_maps: ranges,
+        map(parse_map, |((source, destination), ranges)| {
+            MultiMap::new(source, destination, ranges)
         })(input)
     }
 
-    fn map(&self, number: &u64) -> u64 {
-        for range in &self.number_maps {
-            if let Some(mapped_number) = range.map(number) {
-                return mapped_number;
+    // normalise number_maps (where the first matching map wins) into sorted, non-overlapping segments
+    // which cover all numbers. Gaps become explicit identity segments and neighbouring segments with
+    // the same offset are merged.
+    fn new(source: &str, destination: &str, number_maps: Vec<NumberMap>) -> Self {
+        let mut overlaps = Vec::new();
+        for (first, a) in number_maps.iter().enumerate() {
+            for (second, b) in number_maps.iter().enumerate().skip(first + 1) {
+                if let Some(src) = a.src.overlap(&b.src) {
+                    overlaps.push(Overlap { first, second, src });
+                }
             }
         }
-        number.clone()
-    }
 
-    fn map_range(&self, number_range: &NumberRange) -> Vec<NumberRange> {
-        let mut mapped_ranges = Vec::new();
-        let mut unmapped_ranges = vec![number_range.clone()];
-
-        for number_map in &self.number_maps {
-            let mut new_unmapped_ranges = Vec::new();
-            for unmapped_range in unmapped_ranges {
-                if let Some(mapped_range) = number_map.map_range(&unmapped_range) {
-                    mapped_ranges.push(mapped_range);
-                }
+        let mut covered = IntervalSet::new();
+        let mut segments = Vec::new();
+        let mut origins = Vec::new();
 
-                new_unmapped_ranges.append(&mut unmapped_range.difference(&number_map.src));
+        for (entry, number_map) in number_maps.iter().enumerate() {
+            let uncovered = IntervalSet::from(number_map.src).difference(&covered);
+            segments.extend(uncovered.iter().map(|src| number_map.restrict(src)));
+            origins.extend(uncovered.iter().map(|src| (*src, entry)));
+            covered.insert(number_map.src);
+        }
+        origins.sort_by_key(|(src, _)| src.start);
+
+        let all_numbers = NumberRange { start: 0, end: u64::MAX };
+        segments.extend(covered.complement(&all_numbers).iter().map(|gap| NumberMap {
+            src: *gap,
+            dest: *gap,
+        }));
+
+        segments.sort_by_key(|segment| segment.src.start);
+
+        let mut merged: Vec<NumberMap> = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match merged.last_mut() {
+                Some(last)
+                    if last.offset() == segment.offset()
+                        && last.src.end.checked_add(1) == Some(segment.src.start) =>
+                {
+                    last.src.end = segment.src.end;
+                    last.dest.end = segment.dest.end;
+                }
+                _ => merged.push(segment),
             }
-            unmapped_ranges = new_unmapped_ranges;
         }
 
-        mapped_ranges.append(&mut unmapped_ranges);
-        mapped_ranges
+        MultiMap {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            number_maps: merged,
+            entries: number_maps,
+            origins,
+            overlaps,
+        }
+    }
+
+    // human readable warnings about overlapping entries, which are silently resolved by the first match
+    fn lint(&self) -> Vec<String> {
+        self.overlaps
+            .iter()
+            .map(|overlap| {
+                format!(
+                    "{} map: entries {} and {} overlap on {:?}, entry {} wins",
+                    self.name(),
+                    overlap.first + 1,
+                    overlap.second + 1,
+                    overlap.src,
+                    overlap.first + 1
+                )
+            })
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("{}-to-{}", self.source, self.destination)
+    }
+
+    // the segment which maps number
+    fn find_number_map(&self, number: &u64) -> Option<&NumberMap> {
+        // the segments are sorted, so the first one which does not end before number is the only candidate
+        let i = self
+            .number_maps
+            .partition_point(|number_map| number_map.src.end < *number);
+
+        self.number_maps
+            .get(i)
+            .filter(|number_map| number_map.src.contains(number))
+    }
+
+    // the index of the entry which maps number, None if no entry covers it
+    fn find_entry(&self, number: &u64) -> Option<usize> {
+        let i = self.origins.partition_point(|(src, _)| src.end < *number);
+
+        self.origins
+            .get(i)
+            .filter(|(src, _)| src.contains(number))
+            .map(|(_, entry)| *entry)
+    }
+
+    fn map(&self, number: &u64) -> u64 {
+        self.find_number_map(number)
+            .and_then(|number_map| number_map.map(number))
+            .unwrap_or(*number)
+    }
+
+    // the images of the parts of number_range, in the order of their source numbers
+    fn map_range(&self, number_range: &NumberRange) -> Vec<NumberRange> {
+        self.map_range_by_segment(number_range)
+            .iter()
+            .map(|segment| segment.dest)
+            .collect()
+    }
+
+    // map number_range segment by segment, i.e. the parts of number_range (src) together with what they are mapped onto (dest)
+    fn map_range_by_segment(&self, number_range: &NumberRange) -> Vec<NumberMap> {
+        let first = self
+            .number_maps
+            .partition_point(|number_map| number_map.src.end < number_range.start);
+
+        self.number_maps[first..]
+            .iter()
+            .take_while(|number_map| number_map.src.start <= number_range.end)
+            .filter_map(|number_map| {
+                number_map
+                    .src
+                    .overlap(number_range)
+                    .map(|overlap| number_map.restrict(&overlap))
+            })
+            .collect()
     }
 
     fn chain_number_map(before: &NumberMap, after: &MultiMap) -> Vec<NumberMap> {
@@ -237,29 +385,230 @@ impl MultiMap {
         chained_maps
     }
 
+    // before has to map into the source category of after
     fn chain(before: &MultiMap, after: &MultiMap) -> MultiMap {
-        // for each before number map chain it with after and collect the results in a flat list
-        before.number_maps.iter().fold(
-            MultiMap {
-                number_maps: Vec::new(),
-            },
-            |mut acc, before_map| {
-                let mut chained_map = MultiMap::chain_number_map(before_map, &after);
-                acc.number_maps.append(&mut chained_map);
-                acc
-            },
-        )
+        // for each before number map chain it with after and normalise the results
+        let chained_maps = before
+            .number_maps
+            .iter()
+            .flat_map(|before_map| MultiMap::chain_number_map(before_map, after))
+            .collect();
+
+        MultiMap::new(&before.source, &after.destination, chained_maps)
+    }
+
+    fn invert(&self) -> InverseMultiMap {
+        InverseMultiMap {
+            number_maps: self
+                .number_maps
+                .iter()
+                .map(|number_map| NumberMap {
+                    src: number_map.dest,
+                    dest: number_map.src,
+                })
+                .collect(),
+        }
+    }
+}
+
+// inverse of a MultiMap. Several segments of a MultiMap can map onto the same destination numbers,
+// so a number can have any number of preimages.
+#[derive(Debug, PartialEq, Clone)]
+struct InverseMultiMap {
+    number_maps: Vec<NumberMap>,
+}
+
+impl InverseMultiMap {
+    fn map(&self, number: &u64) -> Vec<u64> {
+        self.number_maps
+            .iter()
+            .filter_map(|number_map| number_map.map(number))
+            .collect()
+    }
+
+    fn map_range(&self, number_range: &NumberRange) -> Vec<NumberRange> {
+        self.number_maps
+            .iter()
+            .filter_map(|number_map| number_map.map_range(number_range))
+            .collect()
+    }
+
+    // numbers in number_range which have more than one preimage
+    fn ambiguous_ranges(&self, number_range: &NumberRange) -> IntervalSet {
+        let mut ambiguous = IntervalSet::new();
+        for (i, a) in self.number_maps.iter().enumerate() {
+            for b in &self.number_maps[i + 1..] {
+                if let Some(overlap) = a.src.overlap(&b.src).and_then(|o| o.overlap(number_range)) {
+                    ambiguous.insert(overlap);
+                }
+            }
+        }
+        ambiguous
+    }
+}
+
+// result of mapping locations back to seeds
+#[derive(Debug, PartialEq, Clone)]
+struct Preimage {
+    seeds: IntervalSet,
+    // (name of the map, destination numbers of that map with more than one preimage)
+    ambiguities: Vec<(String, IntervalSet)>,
+}
+
+// one map applied while translating a number
+#[derive(Debug, PartialEq, Clone)]
+struct TraceStep {
+    source: String,
+    destination: String,
+    input: u64,
+    // the entry of the map (by its index, as written in the almanac) which mapped the number,
+    // None if no entry covers it and it was passed through unchanged
+    entry: Option<(usize, NumberMap)>,
+    output: u64,
+}
+
+// every step of translating a number from one category into another one
+#[derive(Debug, PartialEq, Clone)]
+struct MappingTrace {
+    input: u64,
+    output: u64,
+    steps: Vec<TraceStep>,
+}
+
+// check that the maps form a connected graph of categories without cycles or duplicate maps
+fn validate_category_graph(maps: &[MultiMap]) -> Result<()> {
+    for (i, map) in maps.iter().enumerate() {
+        if maps[..i]
+            .iter()
+            .any(|other| other.source == map.source && other.destination == map.destination)
+        {
+            bail!("map {} is defined more than once", map.name());
+        }
+    }
+
+    // depth first search for cycles, categories on the current path are "in progress"
+    fn visit<'a>(
+        maps: &'a [MultiMap],
+        category: &'a str,
+        in_progress: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        if done.contains(&category) {
+            return Ok(());
+        }
+        if let Some(i) = in_progress.iter().position(|c| *c == category) {
+            bail!("maps form a cycle: {} -> {}", in_progress[i..].join(" -> "), category);
+        }
+
+        in_progress.push(category);
+        for map in maps.iter().filter(|map| map.source == category) {
+            visit(maps, &map.destination, in_progress, done)?;
+        }
+        in_progress.pop();
+        done.push(category);
+        Ok(())
+    }
+
+    let mut done = Vec::new();
+    for map in maps {
+        visit(maps, &map.source, &mut Vec::new(), &mut done)?;
+    }
+
+    // every category has to be reachable from the first one, ignoring the direction of the maps
+    if let Some(first) = maps.first() {
+        let mut reached = vec![first.source.as_str()];
+        let mut i = 0;
+        while i < reached.len() {
+            for map in maps {
+                for (a, b) in [(&map.source, &map.destination), (&map.destination, &map.source)] {
+                    if a == reached[i] && !reached.contains(&b.as_str()) {
+                        reached.push(b);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        if let Some(map) = maps
+            .iter()
+            .find(|map| !reached.contains(&map.source.as_str()))
+        {
+            bail!("map {} is not connected to category {}", map.name(), first.source);
+        }
+    }
+
+    Ok(())
+}
+
+// the maps which translate numbers from one category into another, in the order they have to be applied
+fn find_category_path<'a>(maps: &'a [MultiMap], from: &str, to: &str) -> Result<Vec<&'a MultiMap>> {
+    fn paths<'a: 'b, 'b>(
+        maps: &'a [MultiMap],
+        from: &str,
+        to: &str,
+        visited: &mut Vec<&'b str>,
+    ) -> Vec<Vec<&'a MultiMap>> {
+        if from == to {
+            return vec![Vec::new()];
+        }
+
+        let mut found = Vec::new();
+        for map in maps.iter().filter(|map| map.source == from) {
+            if visited.contains(&map.destination.as_str()) {
+                continue;
+            }
+
+            visited.push(&map.destination);
+            for mut path in paths(maps, &map.destination, to, visited) {
+                path.insert(0, map);
+                found.push(path);
+            }
+            visited.pop();
+        }
+        found
+    }
+
+    for category in [from, to] {
+        if !maps.iter().any(|map| map.source == category || map.destination == category) {
+            bail!("unknown category {}", category);
+        }
+    }
+
+    let mut found = paths(maps, from, to, &mut vec![from]);
+    match found.len() {
+        0 => bail!("no maps lead from {} to {}", from, to),
+        1 => Ok(found.remove(0)),
+        n => bail!(
+            "{} different paths lead from {} to {}, the translation is ambiguous",
+            n,
+            from,
+            to
+        ),
     }
 }
 
+// map a number through all maps of a path, one after the other
+fn walk_path(path: &[&MultiMap], number: u64) -> u64 {
+    path.iter()
+        .fold(number, |mapped_number, map| map.map(&mapped_number))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct Almanac {
     seeds: Vec<u64>,
     maps: Vec<MultiMap>,
 }
 
+impl fmt::Display for Almanac {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let seeds = self.seeds.iter().map(u64::to_string).collect::<Vec<_>>();
+        write!(f, "seeds: {}", seeds.join(" "))?;
+        self.maps.iter().try_for_each(|map| write!(f, "\n\n{}", map))
+    }
+}
+
 impl Almanac {
-    fn parse(input: &str) -> IResult<&str, Self> {
+    fn parse(input: &str) -> ParseResult<'_, Self> {
         let parse_seeds = preceded(tag("seeds: "), separated_list1(space1, parse_u64));
 
         let empty_line = |input| pair(line_ending, line_ending)(input);
@@ -271,44 +620,174 @@ impl Almanac {
         map(parse_almanac, |(seeds, maps)| Almanac { seeds, maps })(input)
     }
 
+    fn validate(&self) -> Result<()> {
+        validate_category_graph(&self.maps)?;
+        find_category_path(&self.maps, SEED, LOCATION)?;
+        Ok(())
+    }
+
+    fn lint(&self) -> Vec<String> {
+        self.maps.iter().flat_map(MultiMap::lint).collect()
+    }
+
+    // the same almanac with all maps from seed to location chained into a single seed-to-location map
+    fn collapse(&self) -> Result<Almanac> {
+        let path = find_category_path(&self.maps, SEED, LOCATION)?;
+        let chained_map = path
+            .into_iter()
+            .cloned()
+            .reduce(|before, after| MultiMap::chain(&before, &after))
+            .unwrap_or_else(|| MultiMap::new(SEED, LOCATION, Vec::new()));
+
+        Ok(Almanac {
+            seeds: self.seeds.clone(),
+            maps: vec![chained_map],
+        })
+    }
+
     fn map(&self, number: u64) -> u64 {
+        self.translate(SEED, LOCATION, number).unwrap()
+    }
+
+    // translate a number from one category into another one
+    fn translate(&self, from: &str, to: &str, number: u64) -> Result<u64> {
+        Ok(walk_path(
+            &find_category_path(&self.maps, from, to)?,
+            number,
+        ))
+    }
+
+    // translate a number from one category into another one and record every step on the way
+    fn trace(&self, from: &str, to: &str, number: u64) -> Result<MappingTrace> {
         let mut mapped_number = number;
-        log::debug!("{}:", number);
+        let mut steps = Vec::new();
+        log::debug!("{} {}:", from, number);
 
-        for map in &self.maps {
+        for map in find_category_path(&self.maps, from, to)? {
+            let entry = map
+                .find_entry(&mapped_number)
+                .map(|entry| (entry, map.entries[entry]));
             let new_mapped_number = map.map(&mapped_number);
 
-            if mapped_number != new_mapped_number {
-                log::debug!("=> {}", mapped_number);
+            if entry.is_some() {
+                log::debug!("=> {} {}", map.destination, new_mapped_number);
             } else {
-                log::debug!("== {}", mapped_number);
+                log::debug!("== {} {}", map.destination, new_mapped_number);
             }
 
+            steps.push(TraceStep {
+                source: map.source.clone(),
+                destination: map.destination.clone(),
+                input: mapped_number,
+                entry,
+                output: new_mapped_number,
+            });
             mapped_number = new_mapped_number;
         }
-        mapped_number
+
+        Ok(MappingTrace {
+            input: number,
+            output: mapped_number,
+            steps,
+        })
+    }
+
+    // translate a range of numbers from one category into another one
+    fn translate_range(&self, from: &str, to: &str, number_range: &NumberRange) -> Result<IntervalSet> {
+        let mut mapped_ranges = IntervalSet::from(*number_range);
+
+        for map in find_category_path(&self.maps, from, to)? {
+            mapped_ranges = mapped_ranges
+                .iter()
+                .flat_map(|range| map.map_range(range))
+                .collect();
+        }
+        Ok(mapped_ranges)
     }
 
     fn get_min_location_number(&self) -> u64 {
-        self.seeds.iter().map(|seed| self.map(*seed)).min().unwrap()
+        // search the path once instead of for every seed
+        let path = find_category_path(&self.maps, SEED, LOCATION).unwrap();
+        self.seeds
+            .iter()
+            .map(|seed| walk_path(&path, *seed))
+            .min()
+            .unwrap()
+    }
+
+    // all seeds (not only the listed ones) which end up at location
+    fn reverse_map(&self, location: u64) -> Result<Preimage> {
+        self.reverse_map_range(&NumberRange {
+            start: location,
+            end: location,
+        })
+    }
+
+    // all seeds (not only the listed ones) which end up in locations
+    fn reverse_map_range(&self, locations: &NumberRange) -> Result<Preimage> {
+        let mut numbers = IntervalSet::from(*locations);
+        let mut ambiguities = Vec::new();
+
+        for map in find_category_path(&self.maps, SEED, LOCATION)?.iter().rev() {
+            let inverse = map.invert();
+
+            let ambiguous = numbers
+                .iter()
+                .flat_map(|range| inverse.ambiguous_ranges(range))
+                .collect::<IntervalSet>();
+            if !ambiguous.is_empty() {
+                log::debug!("{}: ambiguous preimages for {:?}", map.name(), ambiguous);
+                ambiguities.push((map.name(), ambiguous));
+            }
+
+            numbers = numbers
+                .iter()
+                .flat_map(|range| inverse.map_range(range))
+                .collect();
+        }
+
+        ambiguities.reverse();
+        Ok(Preimage {
+            seeds: numbers,
+            ambiguities,
+        })
     }
 }
 
+// what the seed ranges of a Ranged_Almanac are mapped onto
+#[derive(Debug, PartialEq, Clone)]
+struct LocationImage {
+    // all reachable locations, merged
+    locations: IntervalSet,
+    min_location: u64,
+    // the lowest seed which ends up at min_location
+    seed: u64,
+    // the seeds around seed which are mapped onto min_location.. by the same offset
+    seed_range: NumberRange,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct Ranged_Almanac {
     seeds: Vec<NumberRange>,
     maps: Vec<MultiMap>,
 }
 
+impl fmt::Display for Ranged_Almanac {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let seeds = self
+            .seeds
+            .iter()
+            .map(|seeds| format!("{} {}", seeds.start, seeds.end - seeds.start + 1))
+            .collect::<Vec<_>>();
+        write!(f, "seeds: {}", seeds.join(" "))?;
+        self.maps.iter().try_for_each(|map| write!(f, "\n\n{}", map))
+    }
+}
+
 impl Ranged_Almanac {
-    fn parse(input: &str) -> IResult<&str, Self> {
-        let parse_seed_range = map(
-            separated_pair(parse_u64, space1, parse_u64),
-            |(start, length)| NumberRange {
-                start,
-                end: start + length - 1,
-            },
-        );
+    fn parse(input: &str) -> ParseResult<'_, Self> {
+        let parse_seed_range = separated_pair(parse_u64, space1, parse_u64)
+            .map_res_cut(|(start, length)| number_range_with_length(start, length));
 
         let parse_seeds = preceded(tag("seeds: "), separated_list1(space1, parse_seed_range));
 
@@ -324,34 +803,101 @@ impl Ranged_Almanac {
         })(input)
     }
 
+    fn validate(&self) -> Result<()> {
+        validate_category_graph(&self.maps)?;
+        find_category_path(&self.maps, SEED, LOCATION)?;
+        Ok(())
+    }
+
+    fn lint(&self) -> Vec<String> {
+        self.maps.iter().flat_map(MultiMap::lint).collect()
+    }
+
+    // all locations reachable from the seed ranges, the lowest of them and which seeds lead there
+    fn location_image(&self) -> Result<LocationImage> {
+        // chain all maps from seed to location together
+        let path = find_category_path(&self.maps, SEED, LOCATION)?;
+        let chained_map = path
+            .into_iter()
+            .cloned()
+            .reduce(|before, after| MultiMap::chain(&before, &after))
+            .unwrap();
+
+        chained_map.number_maps.iter().for_each(|f| log::debug!("{:?}", f));
+
+        let segments = self
+            .seeds
+            .iter()
+            .flat_map(|seed| chained_map.map_range_by_segment(seed))
+            .collect::<Vec<NumberMap>>();
+
+        let locations = segments
+            .iter()
+            .map(|segment| segment.dest)
+            .collect::<IntervalSet>();
+        log::debug!("mapped ranges: {:?}", locations);
+
+        // every segment maps its seeds linearly, so the start of the segment with the lowest location is the answer
+        let best = segments
+            .iter()
+            .min_by_key(|segment| (segment.dest.start, segment.src.start))
+            .ok_or_else(|| anyhow!("the almanac contains no seeds"))?;
+
+        Ok(LocationImage {
+            min_location: best.dest.start,
+            seed: best.src.start,
+            seed_range: best.src,
+            locations,
+        })
+    }
+
     fn get_min_location_number(&self) -> u64 {
-        // chain all maps together
-        let chained_map = self.maps.iter().cloned().reduce(|before, after| MultiMap::chain(&before, &after)).unwrap();
+        self.location_image().unwrap().min_location
+    }
+}
+
+// parse a complete input with line and column information in the error
+fn parse_complete<T>(input: &str, parser: fn(&str) -> ParseResult<T>) -> Result<T> {
+    final_parser(terminated(parser, multispace0))(input)
+        .map_err(|e: ErrorTree<Location>| anyhow!("could not parse almanac: {}", e))
+}
 
-        chained_map.number_maps.iter().for_each(|f| println!("{:?}", f));
+impl FromStr for Almanac {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let almanac = parse_complete(input, Almanac::parse)?;
+        almanac.validate()?;
+        almanac.lint().iter().for_each(|warning| log::warn!("{}", warning));
+        Ok(almanac)
+    }
+}
 
-        let mapped_ranges = self.seeds.iter().map(|seed| chained_map.map_range(seed)).flatten().collect::<Vec<NumberRange>>();
-        
-        println!("mapped ranges:");
-        mapped_ranges.iter().for_each(|f| println!("{:?}", f));
+impl FromStr for Ranged_Almanac {
+    type Err = anyhow::Error;
 
-        mapped_ranges.iter().map(|range| range.start).min().unwrap()
+    fn from_str(input: &str) -> Result<Self> {
+        let almanac = parse_complete(input, Ranged_Almanac::parse)?;
+        almanac.validate()?;
+        almanac.lint().iter().for_each(|warning| log::warn!("{}", warning));
+        Ok(almanac)
     }
 }
 
 fn get_min_location_number(input: &str) -> u64 {
-    let (_, almanac) = Almanac::parse(input).unwrap();
+    let almanac = input.parse::<Almanac>().unwrap();
     almanac.get_min_location_number()
 }
 
 fn get_min_location_number_ranged(input: &str) -> u64 {
-    let (_, almanac) = Ranged_Almanac::parse(input).unwrap();
+    let almanac = input.parse::<Ranged_Almanac>().unwrap();
     almanac.get_min_location_number()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_number_map_parse() {
@@ -364,11 +910,169 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_number_map_new() {
+        let result = NumberMap::new(0, u64::MAX, 1).unwrap();
+        assert_eq!(result.src, NumberRange { start: u64::MAX, end: u64::MAX });
+
+        let error = NumberMap::new(10, 20, 0).unwrap_err();
+        assert_eq!(error.to_string(), "range starting at 20 has length 0");
+
+        let error = NumberMap::new(u64::MAX - 1, 20, 3).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "range starting at 18446744073709551614 with length 3 exceeds 18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn test_almanac_parse_errors() {
+        let input = "seeds: 79 14
+
+seed-to-soil map:
+50 98 2
+52 50 0
+";
+        let error = input.parse::<Almanac>().unwrap_err().to_string();
+        assert!(error.contains("range starting at 50 has length 0"), "{}", error);
+        assert!(error.contains("line 5, column 1"), "{}", error);
+
+        let input = "seeds: 18446744073709551615 2
+
+seed-to-location map:
+50 98 2
+";
+        let error = input.parse::<Ranged_Almanac>().unwrap_err().to_string();
+        assert!(error.contains("with length 2 exceeds"), "{}", error);
+        assert!(error.contains("line 1, column 8"), "{}", error);
+
+        assert!(input.parse::<Almanac>().is_ok());
+    }
+
+    #[test]
+    fn test_almanac_lint() {
+        let input = "seeds: 79 14
+
+seed-to-soil map:
+50 98 2
+52 50 48
+0 40 20
+
+soil-to-location map:
+0 15 37
+";
+        let almanac = input.parse::<Almanac>().unwrap();
+        assert_eq!(
+            almanac.lint(),
+            vec!["seed-to-soil map: entries 2 and 3 overlap on (50; 59), entry 2 wins"]
+        );
+        // the first matching entry wins
+        assert_eq!(almanac.maps[0].map(&55), 57);
+        // and the trace names it as written, not the normalised segment
+        let trace = almanac.trace(SEED, "soil", 55).unwrap();
+        assert_eq!(
+            trace.steps[0].entry,
+            Some((
+                1,
+                NumberMap {
+                    src: NumberRange { start: 50, end: 97 },
+                    dest: NumberRange { start: 52, end: 99 },
+                }
+            ))
+        );
+        assert_eq!(almanac.trace(SEED, "soil", 45).unwrap().steps[0].entry.unwrap().0, 2);
+
+        // neighbouring entries with the same offset share a segment, but keep their own origin
+        let map = MultiMap::new(
+            "a",
+            "b",
+            vec![
+                NumberMap::new(10, 0, 5).unwrap(),
+                NumberMap::new(15, 5, 5).unwrap(),
+            ],
+        );
+        assert_eq!(map.number_maps.len(), 2);
+        assert_eq!(map.find_entry(&3), Some(0));
+        assert_eq!(map.find_entry(&7), Some(1));
+        assert_eq!(map.find_entry(&10), None);
+
+        let almanac = include_str!("../input/small.txt").parse::<Almanac>().unwrap();
+        assert!(almanac.lint().is_empty());
+    }
+
+    #[test]
+    fn test_multi_map_print() {
+        let input = "seed-to-soil map:\n50 98 2\n52 50 48";
+        let multi_map = MultiMap::parse(input).unwrap().1;
+        assert_eq!(multi_map.to_string(), "seed-to-soil map:\n52 50 48\n50 98 2");
+
+        let identity = MultiMap::new("a", "b", Vec::new());
+        assert_eq!(identity.to_string(), "a-to-b map:\n0 0 1");
+        assert_eq!(MultiMap::parse(&identity.to_string()).unwrap().1, identity);
+
+        let at_the_end = MultiMap::new("a", "b", vec![NumberMap::new(0, u64::MAX, 1).unwrap()]);
+        assert_eq!(at_the_end.to_string(), "a-to-b map:\n0 18446744073709551615 1");
+        assert_eq!(MultiMap::parse(&at_the_end.to_string()).unwrap().1, at_the_end);
+    }
+
+    #[test]
+    fn test_almanac_round_trip() {
+        for input in [
+            include_str!("../input/small.txt").to_string(),
+            std::fs::read_to_string("input/big.txt").expect("Could not open input file"),
+        ] {
+            let almanac = input.parse::<Almanac>().unwrap();
+            assert_eq!(almanac.to_string().parse::<Almanac>().unwrap(), almanac);
+
+            let ranged_almanac = input.parse::<Ranged_Almanac>().unwrap();
+            let printed = ranged_almanac.to_string();
+            assert_eq!(printed.parse::<Ranged_Almanac>().unwrap(), ranged_almanac);
+
+            // the collapsed seed-to-location map can be persisted and gives the same answers
+            let collapsed = almanac.collapse().unwrap().to_string();
+            assert!(collapsed.contains("seed-to-location map:"));
+            assert_eq!(get_min_location_number(&collapsed), get_min_location_number(&input));
+            assert_eq!(
+                get_min_location_number_ranged(&collapsed),
+                get_min_location_number_ranged(&input)
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_multi_map_round_trip(number_maps in number_maps_strategy()) {
+            let multi_map = MultiMap::new("a", "b", number_maps);
+            let printed = multi_map.to_string();
+            prop_assert_eq!(MultiMap::parse(&printed).unwrap().1, multi_map);
+        }
+    }
+
     #[test]
     fn test_multi_map_parse() {
         let input = "seed-to-soil map:\n50 98 2\n52 50 48";
         let expected = MultiMap {
+            source: "seed".to_string(),
+            destination: "soil".to_string(),
             number_maps: vec![
+                NumberMap {
+                    src: NumberRange { start: 0, end: 49 },
+                    dest: NumberRange { start: 0, end: 49 },
+                },
+                NumberMap {
+                    src: NumberRange { start: 50, end: 97 },
+                    dest: NumberRange { start: 52, end: 99 },
+                },
+                NumberMap {
+                    src: NumberRange { start: 98, end: 99 },
+                    dest: NumberRange { start: 50, end: 51 },
+                },
+                NumberMap {
+                    src: NumberRange { start: 100, end: u64::MAX },
+                    dest: NumberRange { start: 100, end: u64::MAX },
+                },
+            ],
+            entries: vec![
                 NumberMap {
                     src: NumberRange { start: 98, end: 99 },
                     dest: NumberRange { start: 50, end: 51 },
@@ -378,9 +1082,18 @@ mod tests {
                     dest: NumberRange { start: 52, end: 99 },
                 },
             ],
+            origins: vec![
+                (NumberRange { start: 50, end: 97 }, 1),
+                (NumberRange { start: 98, end: 99 }, 0),
+            ],
+            overlaps: Vec::new(),
         };
         let result = MultiMap::parse(input).unwrap().1;
         assert_eq!(result, expected);
+        // the equality ignores the leftovers of parsing, so they are checked on their own
+        assert_eq!(result.entries, expected.entries);
+        assert_eq!(result.origins, expected.origins);
+        assert_eq!(result.overlaps, expected.overlaps);
     }
 
     #[test]
@@ -400,34 +1113,30 @@ soil-to-fertilizer map:
         let expected = Almanac {
             seeds: vec![79, 14, 55, 13],
             maps: vec![
-                MultiMap {
-                    number_maps: vec![
-                        NumberMap {
-                            src: NumberRange { start: 98, end: 99 },
-                            dest: NumberRange { start: 50, end: 51 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 50, end: 97 },
-                            dest: NumberRange { start: 52, end: 99 },
-                        },
-                    ],
-                },
-                MultiMap {
-                    number_maps: vec![
-                        NumberMap {
-                            src: NumberRange { start: 15, end: 51 },
-                            dest: NumberRange { start: 0, end: 36 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 52, end: 53 },
-                            dest: NumberRange { start: 37, end: 38 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 0, end: 14 },
-                            dest: NumberRange { start: 39, end: 53 },
-                        },
-                    ],
-                },
+                MultiMap::new("seed", "soil", vec![
+                    NumberMap {
+                        src: NumberRange { start: 98, end: 99 },
+                        dest: NumberRange { start: 50, end: 51 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 50, end: 97 },
+                        dest: NumberRange { start: 52, end: 99 },
+                    },
+                ]),
+                MultiMap::new("soil", "fertilizer", vec![
+                    NumberMap {
+                        src: NumberRange { start: 15, end: 51 },
+                        dest: NumberRange { start: 0, end: 36 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 52, end: 53 },
+                        dest: NumberRange { start: 37, end: 38 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 0, end: 14 },
+                        dest: NumberRange { start: 39, end: 53 },
+                    },
+                ]),
             ],
         };
         let result = Almanac::parse(input).unwrap().1;
@@ -454,34 +1163,30 @@ soil-to-fertilizer map:
                 NumberRange { start: 55, end: 67 },
             ],
             maps: vec![
-                MultiMap {
-                    number_maps: vec![
-                        NumberMap {
-                            src: NumberRange { start: 98, end: 99 },
-                            dest: NumberRange { start: 50, end: 51 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 50, end: 97 },
-                            dest: NumberRange { start: 52, end: 99 },
-                        },
-                    ],
-                },
-                MultiMap {
-                    number_maps: vec![
-                        NumberMap {
-                            src: NumberRange { start: 15, end: 51 },
-                            dest: NumberRange { start: 0, end: 36 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 52, end: 53 },
-                            dest: NumberRange { start: 37, end: 38 },
-                        },
-                        NumberMap {
-                            src: NumberRange { start: 0, end: 14 },
-                            dest: NumberRange { start: 39, end: 53 },
-                        },
-                    ],
-                },
+                MultiMap::new("seed", "soil", vec![
+                    NumberMap {
+                        src: NumberRange { start: 98, end: 99 },
+                        dest: NumberRange { start: 50, end: 51 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 50, end: 97 },
+                        dest: NumberRange { start: 52, end: 99 },
+                    },
+                ]),
+                MultiMap::new("soil", "fertilizer", vec![
+                    NumberMap {
+                        src: NumberRange { start: 15, end: 51 },
+                        dest: NumberRange { start: 0, end: 36 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 52, end: 53 },
+                        dest: NumberRange { start: 37, end: 38 },
+                    },
+                    NumberMap {
+                        src: NumberRange { start: 0, end: 14 },
+                        dest: NumberRange { start: 39, end: 53 },
+                    },
+                ]),
             ],
         };
         let result = Ranged_Almanac::parse(input).unwrap().1;
@@ -530,8 +1235,10 @@ soil-to-fertilizer map:
 
     #[test]
     fn test_multi_map_map_range() {
-        let multi_map = MultiMap {
-            number_maps: vec![
+        let multi_map = MultiMap::new(
+            "a",
+            "b",
+            vec![
                 NumberMap {
                     src: NumberRange { start: 10, end: 20 },
                     dest: NumberRange { start: 50, end: 60 },
@@ -541,17 +1248,344 @@ soil-to-fertilizer map:
                     dest: NumberRange { start: 75, end: 80 },
                 },
             ],
-        };
+        );
         let number_range = NumberRange { start: 15, end: 30 };
         let expected = vec![
             NumberRange { start: 55, end: 60 },
-            NumberRange { start: 75, end: 80 },
             NumberRange { start: 21, end: 24 },
+            NumberRange { start: 75, end: 80 },
         ];
         let result = multi_map.map_range(&number_range);
         assert_eq!(result, expected);
     }
 
+    fn number_maps_strategy() -> impl Strategy<Value = Vec<NumberMap>> {
+        prop::collection::vec((0..64u64, 0..64u64, 1..16u64), 0..5).prop_map(|maps| {
+            maps.iter()
+                .map(|(dest, src, length)| NumberMap {
+                    src: NumberRange { start: *src, end: src + length - 1 },
+                    dest: NumberRange { start: *dest, end: dest + length - 1 },
+                })
+                .collect()
+        })
+    }
+
+    // the unnormalised lookup: the first map containing number wins
+    fn map_first_match(number_maps: &[NumberMap], number: u64) -> u64 {
+        number_maps
+            .iter()
+            .find_map(|number_map| number_map.map(&number))
+            .unwrap_or(number)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_multi_map_is_normalized(number_maps in number_maps_strategy()) {
+            let multi_map = MultiMap::new("a", "b", number_maps.clone());
+            let segments = &multi_map.number_maps;
+
+            prop_assert_eq!(segments.first().unwrap().src.start, 0);
+            prop_assert_eq!(segments.last().unwrap().src.end, u64::MAX);
+            for pair in segments.windows(2) {
+                prop_assert_eq!(pair[0].src.end + 1, pair[1].src.start);
+                prop_assert!(pair[0].offset() != pair[1].offset());
+            }
+
+            for number in 0..96 {
+                prop_assert_eq!(multi_map.map(&number), map_first_match(&number_maps, number));
+            }
+        }
+
+        #[test]
+        fn prop_multi_map_chain_matches_map(
+            before in number_maps_strategy(),
+            after in number_maps_strategy(),
+        ) {
+            let (before, after) = (MultiMap::new("a", "b", before), MultiMap::new("b", "c", after));
+            let chained = MultiMap::chain(&before, &after);
+
+            for number in 0..96 {
+                prop_assert_eq!(chained.map(&number), after.map(&before.map(&number)));
+            }
+        }
+
+        #[test]
+        fn prop_multi_map_map_range_matches_map(
+            number_maps in number_maps_strategy(),
+            (start, end) in (0..64u64, 0..64u64).prop_map(|(a, b)| (a.min(b), a.max(b))),
+        ) {
+            let multi_map = MultiMap::new("a", "b", number_maps);
+
+            let mapped = multi_map
+                .map_range(&NumberRange { start, end })
+                .into_iter()
+                .collect::<IntervalSet>();
+            let expected = (start..=end)
+                .map(|n| NumberRange { start: multi_map.map(&n), end: multi_map.map(&n) })
+                .collect::<IntervalSet>();
+
+            prop_assert_eq!(mapped, expected);
+        }
+    }
+
+    #[test]
+    fn test_multi_map_invert() {
+        // 10..=14 is moved onto 0..=4, which keeps its identity mapping
+        let number_maps = vec![NumberMap {
+            src: NumberRange { start: 10, end: 14 },
+            dest: NumberRange { start: 0, end: 4 },
+        }];
+        let multi_map = MultiMap::new("a", "b", number_maps);
+        let inverse = multi_map.invert();
+
+        assert_eq!(inverse.map(&2), vec![2, 12]);
+        assert_eq!(inverse.map(&12), Vec::<u64>::new());
+        assert_eq!(inverse.map(&20), vec![20]);
+        assert_eq!(
+            inverse.ambiguous_ranges(&NumberRange { start: 3, end: 8 }),
+            IntervalSet::from(NumberRange { start: 3, end: 4 })
+        );
+    }
+
+    #[test]
+    fn test_almanac_reverse_map() {
+        let input = include_str!("../input/small.txt");
+        let (_, almanac) = Almanac::parse(input).unwrap();
+
+        let preimage = almanac.reverse_map(35).unwrap();
+        assert!(preimage.seeds.contains(&13));
+        for seed in preimage.seeds.iter().flat_map(|range| range.start..=range.end) {
+            assert_eq!(almanac.map(seed), 35);
+        }
+
+        let locations = NumberRange { start: 40, end: 60 };
+        let preimage = almanac.reverse_map_range(&locations).unwrap();
+        for seed in 0..200 {
+            assert_eq!(preimage.seeds.contains(&seed), locations.contains(&almanac.map(seed)));
+        }
+    }
+
+    #[test]
+    fn test_almanac_reverse_map_ambiguity() {
+        let input = "seeds: 2
+
+seed-to-location map:
+0 10 5";
+        let (_, almanac) = Almanac::parse(input).unwrap();
+
+        let preimage = almanac.reverse_map(2).unwrap();
+        let expected_seeds = vec![
+            NumberRange { start: 2, end: 2 },
+            NumberRange { start: 12, end: 12 },
+        ];
+        assert_eq!(preimage.seeds, expected_seeds.into_iter().collect());
+
+        let expected_ambiguities = vec![(
+            "seed-to-location".to_string(),
+            IntervalSet::from(NumberRange { start: 2, end: 2 }),
+        )];
+        assert_eq!(preimage.ambiguities, expected_ambiguities);
+
+        assert!(almanac.reverse_map(7).unwrap().ambiguities.is_empty());
+    }
+
+    // almanac with one map per (source, destination) pair, all adding 1 to the numbers 0..=9
+    fn category_almanac(edges: &[(&str, &str)]) -> Almanac {
+        let maps = edges
+            .iter()
+            .map(|(source, destination)| {
+                let number_map = NumberMap {
+                    src: NumberRange { start: 0, end: 9 },
+                    dest: NumberRange { start: 1, end: 10 },
+                };
+                MultiMap::new(source, destination, vec![number_map])
+            })
+            .collect();
+
+        Almanac { seeds: vec![0], maps }
+    }
+
+    #[test]
+    fn test_translate_between_categories() {
+        let input = include_str!("../input/small.txt");
+        let (_, almanac) = Almanac::parse(input).unwrap();
+        almanac.validate().unwrap();
+
+        assert_eq!(almanac.translate("soil", "humidity", 81).unwrap(), 78);
+        assert_eq!(almanac.translate("light", "light", 5).unwrap(), 5);
+        assert_eq!(almanac.translate("seed", "location", 79).unwrap(), 82);
+        assert_eq!(
+            almanac.translate_range("seed", "soil", &NumberRange { start: 97, end: 98 }).unwrap(),
+            vec![NumberRange { start: 50, end: 50 }, NumberRange { start: 99, end: 99 }]
+                .into_iter()
+                .collect()
+        );
+
+        let error = almanac.translate("location", "seed", 82).unwrap_err();
+        assert_eq!(error.to_string(), "no maps lead from location to seed");
+        let error = almanac.translate("seed", "planet", 82).unwrap_err();
+        assert_eq!(error.to_string(), "unknown category planet");
+    }
+
+    #[test]
+    fn test_category_graph_validation() {
+        let dag = category_almanac(&[("seed", "soil"), ("seed", "water"), ("soil", "location")]);
+        dag.validate().unwrap();
+        assert_eq!(dag.translate("seed", "location", 0).unwrap(), 2);
+        assert_eq!(dag.translate("seed", "water", 0).unwrap(), 1);
+
+        let diamond = category_almanac(&[
+            ("seed", "soil"),
+            ("seed", "water"),
+            ("soil", "location"),
+            ("water", "location"),
+        ]);
+        let error = diamond.translate("seed", "location", 0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "2 different paths lead from seed to location, the translation is ambiguous"
+        );
+
+        let cycle = category_almanac(&[
+            ("seed", "soil"),
+            ("soil", "water"),
+            ("water", "soil"),
+            ("soil", "location"),
+        ]);
+        let error = cycle.validate().unwrap_err();
+        assert_eq!(error.to_string(), "maps form a cycle: soil -> water -> soil");
+
+        let disconnected = category_almanac(&[("seed", "location"), ("light", "water")]);
+        let error = disconnected.validate().unwrap_err();
+        assert_eq!(error.to_string(), "map light-to-water is not connected to category seed");
+
+        let duplicate = category_almanac(&[("seed", "location"), ("seed", "location")]);
+        let error = duplicate.validate().unwrap_err();
+        assert_eq!(error.to_string(), "map seed-to-location is defined more than once");
+    }
+
+    #[test]
+    fn small_input_shuffled_maps() {
+        // the maps are chained by their categories, not by the order they appear in
+        let input = include_str!("../input/small.txt");
+        let mut sections = input.split("\n\n").collect::<Vec<_>>();
+        sections[1..].reverse();
+        let input = sections.join("\n\n");
+
+        assert_eq!(get_min_location_number(&input), 35);
+        assert_eq!(get_min_location_number_ranged(&input), 46);
+    }
+
+    #[test]
+    fn test_trace() {
+        let input = include_str!("../input/small.txt");
+        let (_, almanac) = Almanac::parse(input).unwrap();
+
+        let trace = almanac.trace(SEED, LOCATION, 79).unwrap();
+        assert_eq!(trace.output, 82);
+        assert_eq!(
+            trace.steps.iter().map(|step| step.output).collect::<Vec<_>>(),
+            vec![81, 81, 81, 74, 78, 78, 82]
+        );
+        assert_eq!(
+            trace.steps[0],
+            TraceStep {
+                source: "seed".to_string(),
+                destination: "soil".to_string(),
+                input: 79,
+                entry: Some((
+                    1,
+                    NumberMap {
+                        src: NumberRange { start: 50, end: 97 },
+                        dest: NumberRange { start: 52, end: 99 },
+                    }
+                )),
+                output: 81,
+            }
+        );
+        assert_eq!(trace.steps[1].entry, None);
+
+        let table = debug::format_trace_table(&trace);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(
+            lines[0].split('|').map(str::trim).collect::<Vec<_>>(),
+            vec!["category", "input", "map", "output"]
+        );
+        assert_eq!(
+            lines[2].split('|').map(str::trim).collect::<Vec<_>>(),
+            vec!["seed -> soil", "79", "entry 2: (50; 97) --> (52; 99)", "81"]
+        );
+        assert_eq!(
+            lines[3].split('|').map(str::trim).collect::<Vec<_>>(),
+            vec!["soil -> fertilizer", "81", "identity", "81"]
+        );
+
+        let json = debug::trace_to_json(&trace);
+        assert_eq!(json["input"], 79);
+        assert_eq!(json["output"], 82);
+        assert_eq!(json["steps"].as_array().unwrap().len(), 7);
+        assert_eq!(json["steps"][0]["entry"], 2);
+        assert_eq!(json["steps"][0]["map"]["src"]["start"], 50);
+        assert_eq!(json["steps"][0]["map"]["dest"]["end"], 99);
+        assert!(json["steps"][1]["entry"].is_null());
+        assert!(json["steps"][1]["map"].is_null());
+        assert_eq!(json["steps"][6]["destination"], "location");
+    }
+
+    fn check_location_image(ranged_almanac: &Ranged_Almanac) {
+        let image = ranged_almanac.location_image().unwrap();
+        let almanac = Almanac {
+            seeds: Vec::new(),
+            maps: ranged_almanac.maps.clone(),
+        };
+
+        // forward mapping the seed (range) has to lead to the minimum location
+        assert_eq!(almanac.map(image.seed), image.min_location);
+        assert_eq!(image.seed, image.seed_range.start);
+        assert_eq!(
+            almanac.map(image.seed_range.end),
+            image.min_location + (image.seed_range.end - image.seed)
+        );
+        assert_eq!(image.locations.min(), Some(image.min_location));
+        assert!(ranged_almanac
+            .seeds
+            .iter()
+            .any(|seeds| seeds.overlap(&image.seed_range) == Some(image.seed_range)));
+
+        // the location image covers exactly as many numbers as the seed ranges, except where seeds collide
+        let seed_count = ranged_almanac.seeds.iter().cloned().collect::<IntervalSet>().len();
+        assert!(image.locations.len() <= seed_count);
+    }
+
+    #[test]
+    fn small_input_location_image() {
+        let input = include_str!("../input/small.txt");
+        let (_, ranged_almanac) = Ranged_Almanac::parse(input).unwrap();
+        let image = ranged_almanac.location_image().unwrap();
+
+        assert_eq!(image.min_location, 46);
+        assert_eq!(image.seed, 82);
+
+        let almanac = Almanac {
+            seeds: Vec::new(),
+            maps: ranged_almanac.maps.clone(),
+        };
+        for seed in ranged_almanac.seeds.iter().flat_map(|seeds| seeds.start..=seeds.end) {
+            assert!(image.locations.contains(&almanac.map(seed)));
+        }
+        check_location_image(&ranged_almanac);
+    }
+
+    #[test]
+    fn large_input_location_image() {
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        let (_, ranged_almanac) = Ranged_Almanac::parse(&file).unwrap();
+
+        assert_eq!(ranged_almanac.location_image().unwrap().min_location, 60568880);
+        check_location_image(&ranged_almanac);
+    }
+
     #[test]
     fn small_input() {
         env_logger::init();
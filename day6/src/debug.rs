@@ -1 +1,92 @@
-use colored::*;
\ No newline at end of file
+use crate::RaceReport;
+use colored::*;
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+// render the reports as whitespace separated, right aligned columns with one line per race,
+// the column names follow the keys of the json output, where first and last are nested in the window
+pub fn format_report_table(reports: &[RaceReport]) -> String {
+    let optional = |value: Option<u64>| value.map_or("-".to_string(), |value| value.to_string());
+
+    let header = [
+        "time",
+        "record",
+        "first",
+        "last",
+        "ways",
+        "best_hold",
+        "best_distance",
+        "margin",
+        "unbeatable_record",
+    ]
+    .map(String::from);
+    let lines = std::iter::once(header)
+        .chain(reports.iter().map(|report| {
+            [
+                report.duration.to_string(),
+                report.record.to_string(),
+                optional(report.window.as_ref().map(|window| *window.start())),
+                optional(report.window.as_ref().map(|window| *window.end())),
+                ways(report).to_string(),
+                optional(report.best_hold),
+                report.best_distance.to_string(),
+                report.margin.to_string(),
+                report.unbeatable_record.to_string(),
+            ]
+        }))
+        .collect::<Vec<_>>();
+
+    // every column is as wide as its widest value
+    let width = |column: usize| {
+        lines
+            .iter()
+            .map(|line| line[column].len())
+            .max()
+            .unwrap_or(0)
+    };
+    let widths = (0..lines[0].len()).map(width).collect::<Vec<_>>();
+
+    lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:>width$}", value, width = width))
+                .join("  ")
+                + "\n"
+        })
+        .collect()
+}
+
+fn ways(report: &RaceReport) -> u64 {
+    report
+        .window
+        .as_ref()
+        .map_or(0, |window| window.end() - window.start() + 1)
+}
+
+// json numbers stop at u64, larger distances are written as strings
+fn distance_to_json(distance: u128) -> Value {
+    u64::try_from(distance).map_or_else(|_| json!(distance.to_string()), |distance| json!(distance))
+}
+
+pub fn reports_to_json(reports: &[RaceReport]) -> Value {
+    reports
+        .iter()
+        .map(|report| {
+            json!({
+                "time": report.duration,
+                "record": report.record,
+                "window": report.window.as_ref().map(|window| json!({
+                    "first": window.start(),
+                    "last": window.end(),
+                })),
+                "ways": ways(report),
+                "best_hold": report.best_hold,
+                "best_distance": distance_to_json(report.best_distance),
+                "margin": distance_to_json(report.margin),
+                "unbeatable_record": distance_to_json(report.unbeatable_record),
+            })
+        })
+        .collect()
+}
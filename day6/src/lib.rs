@@ -1,62 +1,267 @@
+use anyhow::{anyhow, bail, Result};
 use nom::{
     bytes::complete::tag,
-    character::complete::{line_ending, space1, u64 as u64_parser},
-    combinator::map,
+    character::complete::{digit1, line_ending, multispace0, space0, space1},
+    combinator::all_consuming,
     multi::separated_list1,
-    sequence::{preceded, separated_pair, terminated},
+    sequence::{delimited, separated_pair, terminated, tuple},
 };
+use std::ops::RangeInclusive;
 mod debug;
 
-fn parse_races(input: &str) -> Vec<(u64, u64)> {
-    let time_label =
-        terminated::<&str, _, _, (&str, nom::error::ErrorKind), _, _>(tag("Time:"), space1);
-    let distance_label = terminated(tag("Distance:"), space1);
-
-    let time_parser = preceded(time_label, separated_list1(space1, u64_parser));
-    let distance_parser = preceded(distance_label, separated_list1(space1, u64_parser));
-
-    map(
-        separated_pair(time_parser, line_ending, distance_parser),
-        |(time, distance)| {
-            time.iter()
-                .zip(distance.iter())
-                .map(|(t, d)| (*t, *d))
-                .collect()
-        },
-    )(input)
-    .unwrap()
-    .1
-}
-
-fn solve_quadratic_formula(a: f64, b: f64, c: f64) -> (f64, f64) {
-    let discriminant = b.powf(2.0) - 4.0 * a * c;
-    let root = (discriminant as f64).sqrt();
-    let denominator = 2.0 * a;
-    let x1 = (-b + root) / denominator;
-    let x2 = (-b - root) / denominator;
-    (x1, x2)
-}
-
-fn calculate_bounds(duration: u64, record: u64) -> (u64, u64) {
-    // t * (d - t) = r => t^2 - d * t + r = 0
-    // a => 1; b => -d; c => r
-    let duration = duration as f64;
-    let record = record as f64;
-    let (x1, x2) = solve_quadratic_formula(1.0, -duration, record);
-    println!("ff {x1} {x2}");
-    (x1.ceil() as u64 - 1, x2.floor() as u64 + 1)
-}
-
-fn calc_record_product(input: &str) -> u64 {
-    let races = parse_races(input);
-
-    races
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kerning {
+    // every column is its own race
+    Spaced,
+    // the digits of a line form one number, there is only one race
+    Joined,
+}
+
+fn parse_numbers(digit_groups: Vec<&str>, kerning: Kerning) -> Result<Vec<u64>> {
+    let numbers = match kerning {
+        Kerning::Spaced => digit_groups.into_iter().map(String::from).collect(),
+        Kerning::Joined => vec![digit_groups.concat()],
+    };
+    numbers
+        .iter()
+        .map(|digits| {
+            digits
+                .parse()
+                .map_err(|e| anyhow!("invalid number {digits}: {e}"))
+        })
+        .collect()
+}
+
+fn parse_races(input: &str, kerning: Kerning) -> Result<Vec<(u64, u64)>> {
+    let numbers = || separated_list1(space1, digit1);
+    let time_line = tuple((tag("Time:"), space1, numbers()));
+    let distance_line = tuple((tag("Distance:"), space1, numbers()));
+
+    let (_, ((_, _, time), (_, _, distance))) = all_consuming(delimited(
+        multispace0,
+        separated_pair(time_line, terminated(space0, line_ending), distance_line),
+        multispace0,
+    ))(input)
+    .map_err(|e: nom::Err<nom::error::Error<&str>>| {
+        anyhow!("could not parse race sheet: {}", e.to_owned())
+    })?;
+
+    let time = parse_numbers(time, kerning)?;
+    let distance = parse_numbers(distance, kerning)?;
+    if time.len() != distance.len() {
+        bail!(
+            "race sheet lists {} times but {} distances",
+            time.len(),
+            distance.len()
+        );
+    }
+
+    Ok(time.into_iter().zip(distance).collect())
+}
+
+fn isqrt(n: u128) -> u128 {
+    // newton iteration, starting above the root so it decreases monotonically
+    if n < 2 {
+        return n;
+    }
+    let mut x = 1u128 << ((128 - n.leading_zeros()).div_ceil(2));
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+fn beats_record(duration: u64, record: u64, hold: u64) -> bool {
+    // hold * (duration - hold) < 2^126, so this can't overflow
+    hold as u128 * (duration - hold) as u128 > record as u128
+}
+
+fn calculate_bounds(duration: u64, record: u64) -> Option<RangeInclusive<u64>> {
+    // t * (d - t) > r => t^2 - d * t + r < 0
+    // the winning holds lie strictly between (d - sqrt(d^2 - 4r)) / 2 and (d + sqrt(d^2 - 4r)) / 2
+    let d = duration as u128;
+    let discriminant = (d * d).checked_sub(4 * record as u128)?;
+
+    // floor of the lower root, at most one off of the first winning hold
+    let mut first = ((d - isqrt(discriminant)) / 2) as u64;
+    while first > 0 && beats_record(duration, record, first - 1) {
+        first -= 1;
+    }
+    while first <= duration / 2 && !beats_record(duration, record, first) {
+        first += 1;
+    }
+    if first > duration / 2 {
+        return None;
+    }
+
+    // the distance is symmetric around duration / 2
+    Some(first..=duration - first)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RaceModel {
+    // speed gained per millisecond the button is held
+    charge_rate: u64,
+    // the boat never goes faster than this, speeds also saturate at u64::MAX
+    max_speed: Option<u64>,
+    // speed lost per millisecond once the boat is moving, it stops at 0
+    drag: u64,
+    // releasing the button earlier than this doesn't move the boat at all
+    min_hold: u64,
+}
+
+impl Default for RaceModel {
+    // the rules from the puzzle
+    fn default() -> Self {
+        RaceModel {
+            charge_rate: 1,
+            max_speed: None,
+            drag: 0,
+            min_hold: 0,
+        }
+    }
+}
+
+// first value in the range for which pred is false, pred has to be true then false
+fn partition_point(range: RangeInclusive<u64>, pred: impl Fn(u64) -> bool) -> u64 {
+    let (mut low, mut high) = (*range.start() as u128, *range.end() as u128 + 1);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(mid as u64) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low as u64
+}
+
+impl RaceModel {
+    fn top_speed(&self) -> u64 {
+        self.max_speed.unwrap_or(u64::MAX)
+    }
+
+    fn speed(&self, hold: u64) -> u128 {
+        (self.charge_rate as u128 * hold as u128).min(self.top_speed() as u128)
+    }
+
+    fn distance(&self, duration: u64, hold: u64) -> u128 {
+        if hold < self.min_hold || hold > duration {
+            return 0;
+        }
+        let speed = self.speed(hold);
+        let travel_time = (duration - hold) as u128;
+        if self.drag == 0 {
+            return speed * travel_time;
+        }
+
+        // the boat moves for m milliseconds, covering speed, speed - drag, ...
+        let drag = self.drag as u128;
+        let m = travel_time.min(speed.div_ceil(drag));
+        if m == 0 {
+            return 0;
+        }
+        // sum = m * (2 * speed - drag * (m - 1)) / 2, halved before multiplying so it can't overflow
+        let twice_average = 2 * speed - drag * (m - 1);
+        if m.is_multiple_of(2) {
+            (m / 2) * twice_average
+        } else {
+            m * (twice_average / 2)
+        }
+    }
+
+    fn winning_window(&self, duration: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        if self.charge_rate == 0 || self.min_hold > duration {
+            return None;
+        }
+
+        if self.drag == 0 && self.charge_rate as u128 * duration as u128 <= self.top_speed() as u128
+        {
+            // the speed never hits the cap, so this is c * t * (d - t) > r <=> t * (d - t) > r / c
+            let window = calculate_bounds(duration, record / self.charge_rate)?;
+            let first = (*window.start()).max(self.min_hold);
+            return (first <= *window.end()).then_some(first..=*window.end());
+        }
+
+        // no closed form, but the distance rises up to the best hold and falls after it
+        let peak = self.best_hold(duration)?;
+        let record = record as u128;
+        if self.distance(duration, peak) <= record {
+            return None;
+        }
+        let first = partition_point(self.min_hold..=peak, |hold| {
+            self.distance(duration, hold) <= record
+        });
+        let last = partition_point(peak..=duration, |hold| {
+            self.distance(duration, hold) > record
+        });
+        Some(first..=last - 1)
+    }
+
+    fn best_hold(&self, duration: u64) -> Option<u64> {
+        if self.min_hold > duration {
+            return None;
+        }
+        Some(partition_point(self.min_hold..=duration, |hold| {
+            hold < duration && self.distance(duration, hold + 1) > self.distance(duration, hold)
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RaceReport {
+    duration: u64,
+    record: u64,
+    window: Option<RangeInclusive<u64>>,
+    // None if the minimum hold doesn't fit into the race
+    best_hold: Option<u64>,
+    best_distance: u128,
+    // how far the best hold beats the record, 0 if it doesn't
+    margin: u128,
+    // the smallest record that can't be beaten anymore
+    unbeatable_record: u128,
+}
+
+impl RaceModel {
+    fn report(&self, duration: u64, record: u64) -> RaceReport {
+        let best_hold = self.best_hold(duration);
+        let best_distance = best_hold.map_or(0, |hold| self.distance(duration, hold));
+        RaceReport {
+            duration,
+            record,
+            window: self.winning_window(duration, record),
+            best_hold,
+            best_distance,
+            margin: best_distance.saturating_sub(record as u128),
+            unbeatable_record: best_distance,
+        }
+    }
+}
+
+fn report_races(input: &str, kerning: Kerning, model: &RaceModel) -> Result<Vec<RaceReport>> {
+    let races = parse_races(input, kerning)?;
+
+    Ok(races
+        .iter()
+        .map(|(duration, record)| model.report(*duration, *record))
+        .collect())
+}
+
+fn calc_record_product(input: &str, kerning: Kerning) -> Result<u64> {
+    calc_record_product_with_model(input, kerning, &RaceModel::default())
+}
+
+fn calc_record_product_with_model(input: &str, kerning: Kerning, model: &RaceModel) -> Result<u64> {
+    let races = parse_races(input, kerning)?;
+
+    Ok(races
         .iter()
-        .inspect(|(duration, record)| print!("{} {} => ", duration, record))
-        .map(|(duration, record)| calculate_bounds(*duration, *record))
-        .inspect(|(x1, x2)| println!("{} {}", x1, x2))
-        .map(|(x1, x2)| x1 - x2 + 1)
-        .product()
+        .map(|(duration, record)| model.winning_window(*duration, *record))
+        .map(|window| window.map_or(0, |window| window.end() - window.start() + 1))
+        .product())
 }
 
 #[cfg(test)]
@@ -68,18 +273,319 @@ mod tests {
         let input = "Time:      7  15   30
 Distance:  9  40  200";
         let expected = vec![(7, 9), (15, 40), (30, 200)];
-        let result = parse_races(input);
+        let result = parse_races(input, Kerning::Spaced).unwrap();
         assert_eq!(result, expected);
+
+        let result = parse_races(input, Kerning::Joined).unwrap();
+        assert_eq!(result, vec![(71530, 940200)]);
     }
 
     #[test]
-    fn test_solve_quadratic_formula() {
-        let a = 1.0;
-        let b = 2.0;
-        let c = 1.0;
-        let expected = (-1.0, -1.0);
-        let result = solve_quadratic_formula(a, b, c);
-        assert_eq!(result, expected);
+    fn test_parse_races_errors() {
+        let input = "Time:      7  15   30\nDistance:  9  40";
+        assert_eq!(
+            parse_races(input, Kerning::Spaced).unwrap_err().to_string(),
+            "race sheet lists 3 times but 2 distances"
+        );
+        // joined, both lines are a single race again
+        assert_eq!(
+            parse_races(input, Kerning::Joined).unwrap(),
+            vec![(71530, 940)]
+        );
+
+        let input = "Time:      99999999999  99999999999\nDistance:  9  40";
+        assert_eq!(
+            parse_races(input, Kerning::Joined).unwrap_err().to_string(),
+            "invalid number 9999999999999999999999: number too large to fit in target type"
+        );
+
+        assert!(parse_races("Time: 7\nDistance: x", Kerning::Spaced).is_err());
+        assert!(parse_races("Distance: 9\nTime: 7", Kerning::Spaced).is_err());
+    }
+
+    #[test]
+    fn test_isqrt() {
+        for n in 0..10_000u128 {
+            let root = isqrt(n);
+            assert!(
+                root * root <= n && (root + 1) * (root + 1) > n,
+                "isqrt({n}) = {root}"
+            );
+        }
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+        assert_eq!(
+            isqrt((u64::MAX as u128) * (u64::MAX as u128)),
+            u64::MAX as u128
+        );
+        assert_eq!(isqrt((1 << 106) - 1), (1 << 53) - 1);
+    }
+
+    fn brute_force_bounds(duration: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let winning = (0..=duration)
+            .filter(|hold| beats_record(duration, record, *hold))
+            .collect::<Vec<_>>();
+        Some(*winning.first()?..=*winning.last()?)
+    }
+
+    #[test]
+    fn test_calculate_bounds() {
+        assert_eq!(calculate_bounds(7, 9), Some(2..=5));
+        assert_eq!(calculate_bounds(15, 40), Some(4..=11));
+        assert_eq!(calculate_bounds(30, 200), Some(11..=19));
+        assert_eq!(calculate_bounds(0, 0), None);
+        assert_eq!(calculate_bounds(10, 25), None);
+        assert_eq!(calculate_bounds(10, 24), Some(5..=5));
+    }
+
+    #[test]
+    fn test_calculate_bounds_brute_force() {
+        for duration in 0..80 {
+            for record in 0..=duration * duration / 4 + 2 {
+                assert_eq!(
+                    calculate_bounds(duration, record),
+                    brute_force_bounds(duration, record),
+                    "duration {duration}, record {record}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_calculate_bounds_exact_roots() {
+        // records hit exactly at an integer hold time, far beyond f64 precision
+        let cases = [
+            (1 << 60, 1 << 20),
+            ((1 << 54) + 1, (1 << 53) - 3),
+            (u64::MAX, 1),
+            (u64::MAX, u64::MAX / 3),
+            (u64::MAX - 1, u64::MAX / 2),
+            (1 << 33, 1 << 32),
+        ];
+        for (duration, hold) in cases {
+            let record = (hold as u128 * (duration - hold) as u128).min(u64::MAX as u128) as u64;
+            let window = calculate_bounds(duration, record).unwrap();
+            let (first, last) = (*window.start(), *window.end());
+            assert!(beats_record(duration, record, first));
+            assert!(!beats_record(duration, record, first - 1));
+            assert!(beats_record(duration, record, last));
+            assert!(!beats_record(duration, record, last + 1));
+        }
+    }
+
+    fn brute_force_window(
+        model: &RaceModel,
+        duration: u64,
+        record: u64,
+    ) -> Option<RangeInclusive<u64>> {
+        let winning = (0..=duration)
+            .filter(|hold| model.distance(duration, *hold) > record as u128)
+            .collect::<Vec<_>>();
+        Some(*winning.first()?..=*winning.last()?)
+    }
+
+    #[test]
+    fn test_race_model_distance() {
+        let model = RaceModel::default();
+        assert_eq!(model.distance(7, 2), 10);
+
+        let model = RaceModel {
+            charge_rate: 3,
+            max_speed: Some(10),
+            drag: 0,
+            min_hold: 2,
+        };
+        assert_eq!(model.distance(10, 1), 0);
+        assert_eq!(model.distance(10, 2), 6 * 8);
+        assert_eq!(model.distance(10, 5), 10 * 5);
+
+        let model = RaceModel {
+            charge_rate: 1,
+            max_speed: None,
+            drag: 2,
+            min_hold: 0,
+        };
+        // 5 + 3 + 1, then the boat stands still
+        assert_eq!(model.distance(20, 5), 9);
+        // 5 + 3, the race ends first
+        assert_eq!(model.distance(7, 5), 8);
+
+        // exact even where the intermediate products would overflow
+        let model = RaceModel {
+            charge_rate: u64::MAX,
+            max_speed: None,
+            drag: 1,
+            min_hold: 0,
+        };
+        let m = (u64::MAX - 1) as u128;
+        let speed = u64::MAX as u128;
+        assert_eq!(model.distance(u64::MAX, 1), m * speed - m * (m - 1) / 2);
+    }
+
+    #[test]
+    fn test_race_model_default() {
+        let model = RaceModel::default();
+        for duration in 0..60 {
+            for record in 0..=duration * duration / 4 + 2 {
+                assert_eq!(
+                    model.winning_window(duration, record),
+                    calculate_bounds(duration, record)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_race_model_brute_force() {
+        for charge_rate in 0..4 {
+            for max_speed in [None, Some(0), Some(3), Some(7)] {
+                for drag in 0..4 {
+                    for min_hold in [0, 2, 9] {
+                        let model = RaceModel {
+                            charge_rate,
+                            max_speed,
+                            drag,
+                            min_hold,
+                        };
+                        for duration in 0..25 {
+                            let best = (0..=duration)
+                                .map(|hold| model.distance(duration, hold))
+                                .max()
+                                .unwrap();
+                            if let Some(hold) = model.best_hold(duration) {
+                                assert_eq!(
+                                    model.distance(duration, hold),
+                                    best,
+                                    "{model:?} {duration}"
+                                );
+                            }
+                            for record in 0..=best as u64 + 1 {
+                                assert_eq!(
+                                    model.winning_window(duration, record),
+                                    brute_force_window(&model, duration, record),
+                                    "{model:?}, duration {duration}, record {record}"
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_race_model_variants() {
+        let input = include_str!("../input/small.txt");
+        let model = RaceModel {
+            charge_rate: 2,
+            ..RaceModel::default()
+        };
+        // 7ms race, 2 * t * (7 - t) > 9 for t in 1..=6
+        assert_eq!(model.winning_window(7, 9), Some(1..=6));
+        assert_eq!(
+            calc_record_product_with_model(input, Kerning::Spaced, &model).unwrap(),
+            6 * 12 * 23
+        );
+
+        let model = RaceModel {
+            min_hold: 12,
+            ..RaceModel::default()
+        };
+        assert_eq!(
+            calc_record_product_with_model(input, Kerning::Spaced, &model).unwrap(),
+            0
+        );
+        assert_eq!(model.winning_window(30, 200), Some(12..=19));
+    }
+
+    #[test]
+    fn test_race_reports() {
+        let input = include_str!("../input/small.txt");
+        let reports = report_races(input, Kerning::Spaced, &RaceModel::default()).unwrap();
+        assert_eq!(
+            reports[0],
+            RaceReport {
+                duration: 7,
+                record: 9,
+                window: Some(2..=5),
+                best_hold: Some(3),
+                best_distance: 12,
+                margin: 3,
+                unbeatable_record: 12,
+            }
+        );
+        assert_eq!(reports[2].window, Some(11..=19));
+        assert_eq!(reports[2].best_hold, Some(15));
+        assert_eq!(reports[2].margin, 25);
+
+        let model = RaceModel {
+            min_hold: 12,
+            ..RaceModel::default()
+        };
+        let reports = report_races(input, Kerning::Spaced, &model).unwrap();
+        assert_eq!(reports[0].window, None);
+        assert_eq!(reports[0].best_hold, None);
+        assert_eq!(reports[0].margin, 0);
+        assert_eq!(reports[1].best_hold, Some(12));
+        assert_eq!(reports[1].margin, 0);
+        assert_eq!(reports[1].unbeatable_record, 36);
+
+        // the unbeatable record is the first one without a window
+        for report in report_races(input, Kerning::Joined, &RaceModel::default()).unwrap() {
+            let model = RaceModel::default();
+            let record = report.unbeatable_record as u64;
+            assert!(model.winning_window(report.duration, record - 1).is_some());
+            assert!(model.winning_window(report.duration, record).is_none());
+        }
+    }
+
+    #[test]
+    fn test_race_report_export() {
+        let input = include_str!("../input/small.txt");
+        let reports = report_races(input, Kerning::Spaced, &RaceModel::default()).unwrap();
+
+        let table = debug::format_report_table(&reports);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0].split_whitespace().collect::<Vec<_>>(),
+            vec![
+                "time",
+                "record",
+                "first",
+                "last",
+                "ways",
+                "best_hold",
+                "best_distance",
+                "margin",
+                "unbeatable_record"
+            ]
+        );
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            vec!["7", "9", "2", "5", "4", "3", "12", "3", "12"]
+        );
+        // the columns are right aligned
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+
+        let json = debug::reports_to_json(&reports);
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        assert_eq!(json[1]["window"]["first"], 4);
+        assert_eq!(json[1]["window"]["last"], 11);
+        assert_eq!(json[1]["ways"], 8);
+        assert_eq!(json[2]["best_hold"], 15);
+        assert_eq!(json[2]["margin"], 25);
+
+        let model = RaceModel {
+            charge_rate: u64::MAX,
+            ..RaceModel::default()
+        };
+        // full speed after 1ms already
+        let json = debug::reports_to_json(&[model.report(u64::MAX, 0)]);
+        assert_eq!(json[0]["best_hold"], 1);
+        assert_eq!(
+            json[0]["best_distance"],
+            (u64::MAX as u128 * (u64::MAX - 1) as u128).to_string()
+        );
     }
 
     #[test]
@@ -88,27 +594,49 @@ Distance:  9  40  200";
 
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/small.txt");
-        assert_eq!(calc_record_product(input), 288)
+        assert_eq!(calc_record_product(input, Kerning::Spaced).unwrap(), 288)
     }
 
     #[test]
     fn large_input() {
         // You can also read the file completely into memory
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
-        assert_eq!(calc_record_product(&file), 1624896)
+        assert_eq!(
+            calc_record_product(&file, Kerning::Spaced).unwrap(),
+            1624896
+        )
     }
 
     #[test]
     fn small_input_kerning() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/small_kerning.txt");
-        assert_eq!(calc_record_product(input), 71503)
+        assert_eq!(calc_record_product(input, Kerning::Spaced).unwrap(), 71503)
     }
 
     #[test]
     fn large_input_kerning() {
         // You can also read the file completely into memory
-        let file = std::fs::read_to_string("input/big_kerning.txt").expect("Could not open input file");
-        assert_eq!(calc_record_product(&file), 32583852)
+        let file =
+            std::fs::read_to_string("input/big_kerning.txt").expect("Could not open input file");
+        assert_eq!(
+            calc_record_product(&file, Kerning::Spaced).unwrap(),
+            32583852
+        )
+    }
+
+    #[test]
+    fn small_input_joined() {
+        let input = include_str!("../input/small.txt");
+        assert_eq!(calc_record_product(input, Kerning::Joined).unwrap(), 71503)
+    }
+
+    #[test]
+    fn large_input_joined() {
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        assert_eq!(
+            calc_record_product(&file, Kerning::Joined).unwrap(),
+            32583852
+        )
     }
 }
@@ -1 +1,37 @@
-use colored::*;
\ No newline at end of file
+use crate::RankingReport;
+use colored::*;
+
+// quote fields that contain separators, quotes or line breaks
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// one row per hand, weakest first
+pub fn report_to_csv(report: &RankingReport) -> String {
+    let mut csv = String::from("rank,cards,type,natural_type,promoted,bid,winnings\n");
+    for hand in &report.hands {
+        csv += &format!(
+            "{},{},{:?},{:?},{},{},{}\n",
+            hand.rank,
+            csv_field(&hand.cards),
+            hand.hand_type,
+            hand.natural_type,
+            hand.promoted(),
+            hand.bid_amount,
+            hand.winnings
+        );
+    }
+    csv
+}
+
+pub fn histogram_to_csv(report: &RankingReport) -> String {
+    let mut csv = String::from("type,count\n");
+    for (hand_type, count) in &report.histogram {
+        csv += &format!("{:?},{}\n", hand_type, count);
+    }
+    csv
+}
@@ -1,130 +1,453 @@
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 mod debug;
 
-// possible card types
-// A, K, Q, J, T, 9, 8, 7, 6, 5, 4, 3, or 2
+// a card as seen by a ruleset, its rank is its position in the ruleset's alphabet
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
-enum CardType {
-    Joker,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-    Jack,
-    Queen,
-    King,
-    Ace,
+struct Card {
+    rank: u8,
+    symbol: char,
+    // only in suited rulesets
+    suit: Option<char>,
+    // stands in for whichever card makes the best hand
+    wildcard: bool,
 }
 
-impl CardType {
-    fn from_char(c: char) -> Option<CardType> {
-        match c {
-            'A' => Some(CardType::Ace),
-            'K' => Some(CardType::King),
-            'Q' => Some(CardType::Queen),
-            'J' => Some(CardType::Jack),
-            'T' => Some(CardType::Ten),
-            '9' => Some(CardType::Nine),
-            '8' => Some(CardType::Eight),
-            '7' => Some(CardType::Seven),
-            '6' => Some(CardType::Six),
-            '5' => Some(CardType::Five),
-            '4' => Some(CardType::Four),
-            '3' => Some(CardType::Three),
-            '2' => Some(CardType::Two),
-            _ => None,
-        }
-    }
-
-    fn from_char_joker(c: char) -> Option<CardType> {
-        match c {
-            'A' => Some(CardType::Ace),
-            'K' => Some(CardType::King),
-            'Q' => Some(CardType::Queen),
-            'T' => Some(CardType::Ten),
-            '9' => Some(CardType::Nine),
-            '8' => Some(CardType::Eight),
-            '7' => Some(CardType::Seven),
-            '6' => Some(CardType::Six),
-            '5' => Some(CardType::Five),
-            '4' => Some(CardType::Four),
-            '3' => Some(CardType::Three),
-            '2' => Some(CardType::Two),
-            'J' => Some(CardType::Joker),
-            _ => None,
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if let Some(suit) = self.suit {
+            write!(f, "{}", suit)?;
         }
+        Ok(())
     }
 }
 
-fn parse_cards(input: &str) -> [CardType; 5] {
-    let cards = input
-        .chars()
-        .map(|c| CardType::from_char(c).unwrap())
-        .collect::<Vec<_>>();
-    let cards = cards.as_slice();
-    let mut hand = [CardType::Ace; 5];
-    for i in 0..5 {
-        hand[i] = cards[i];
-    }
-    hand
+// the sort key has room for the hand type, 10 group sizes of half a byte and 10 one byte ranks
+const MAX_HAND_SIZE: usize = 10;
+
+// which cards exist, how they rank and which of them are wild. Camel cards order hands of the same type
+// card by card in the order they were dealt, poker mode compares them like poker does: straights by
+// their top card, flushes and high cards from their highest card down, everything else by its groups
+// (the larger one first, then the higher one) and then the kickers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ruleset {
+    // card symbols, from the weakest to the strongest
+    alphabet: Vec<char>,
+    wildcards: Vec<char>,
+    hand_size: usize,
+    // poker mode, every card is followed by one of these, empty for camel cards
+    suits: Vec<char>,
+    // card symbols in straight order, the last one can also come before the first
+    sequence: Vec<char>,
+}
+
+impl Ruleset {
+    fn new(alphabet: &str, wildcards: &str, hand_size: usize) -> Result<Ruleset> {
+        let alphabet = alphabet.chars().collect::<Vec<_>>();
+        let wildcards = wildcards.chars().collect::<Vec<_>>();
+
+        if alphabet.len() > 256 {
+            bail!(
+                "alphabet has {} cards, at most 256 are supported",
+                alphabet.len()
+            );
+        }
+        for (i, symbol) in alphabet.iter().enumerate() {
+            if alphabet[..i].contains(symbol) {
+                bail!("card '{symbol}' appears twice in the alphabet");
+            }
+        }
+        if let Some(symbol) = wildcards.iter().find(|c| !alphabet.contains(c)) {
+            bail!("wildcard '{symbol}' is not in the alphabet");
+        }
+        if !(1..=MAX_HAND_SIZE).contains(&hand_size) {
+            bail!("hands of {hand_size} cards are not supported, use 1 to {MAX_HAND_SIZE}");
+        }
+
+        Ok(Ruleset {
+            sequence: alphabet.clone(),
+            alphabet,
+            wildcards,
+            hand_size,
+            suits: Vec::new(),
+        })
+    }
+
+    fn with_suits(self, suits: &str, sequence: &str) -> Result<Ruleset> {
+        let suits = suits.chars().collect::<Vec<_>>();
+        let sequence = sequence.chars().collect::<Vec<_>>();
+
+        if suits.is_empty() {
+            bail!("a suited ruleset needs at least one suit");
+        }
+        for (i, suit) in suits.iter().enumerate() {
+            if suits[..i].contains(suit) {
+                bail!("suit '{suit}' appears twice");
+            }
+        }
+        for (i, symbol) in sequence.iter().enumerate() {
+            if sequence[..i].contains(symbol) {
+                bail!("card '{symbol}' appears twice in the straight order");
+            }
+        }
+        // wildcards never need a place in a straight, they fill the gaps
+        if let Some(symbol) = self
+            .alphabet
+            .iter()
+            .find(|c| !self.wildcards.contains(c) && !sequence.contains(c))
+        {
+            bail!("card '{symbol}' is missing from the straight order");
+        }
+
+        Ok(Ruleset {
+            suits,
+            sequence,
+            ..self
+        })
+    }
+
+    // A, K, Q, J, T, 9, 8, 7, 6, 5, 4, 3, or 2
+    fn standard() -> Ruleset {
+        Ruleset::new("23456789TJQKA", "", 5).unwrap()
+    }
+
+    // J is the weakest card, but it can act like any other card
+    fn joker() -> Ruleset {
+        Ruleset::new("J23456789TQKA", "J", 5).unwrap()
+    }
+
+    // standard poker, aces are high but can also start a straight
+    fn poker() -> Ruleset {
+        Ruleset::standard()
+            .with_suits("cdhs", "23456789TJQKA")
+            .unwrap()
+    }
+
+    fn poker_joker() -> Ruleset {
+        Ruleset::joker()
+            .with_suits("cdhs", "23456789TJQKA")
+            .unwrap()
+    }
+
+    fn card(&self, symbol: char) -> Option<Card> {
+        let rank = self.alphabet.iter().position(|c| *c == symbol)?;
+        Some(Card {
+            rank: rank as u8,
+            symbol,
+            suit: None,
+            wildcard: self.wildcards.contains(&symbol),
+        })
+    }
+
+    fn parse_card(&self, chars: &mut impl Iterator<Item = char>, input: &str) -> Result<Card> {
+        // the caller only asks while there are chars left
+        let symbol = chars.next().unwrap();
+        let mut card = self
+            .card(symbol)
+            .ok_or_else(|| anyhow!("unknown card '{symbol}' in hand {input}"))?;
+        if !self.suits.is_empty() {
+            let suit = chars
+                .next()
+                .ok_or_else(|| anyhow!("card '{symbol}' in hand {input} has no suit"))?;
+            if !self.suits.contains(&suit) {
+                bail!("unknown suit '{suit}' in hand {input}");
+            }
+            card.suit = Some(suit);
+        }
+        Ok(card)
+    }
+
+    fn parse_cards(&self, input: &str) -> Result<Vec<Card>> {
+        let mut chars = input.chars().peekable();
+        let mut cards: Vec<Card> = Vec::new();
+        while chars.peek().is_some() {
+            let card = self.parse_card(&mut chars, input)?;
+            // a suited card exists only once in the deck, wildcards are as many as needed
+            if card.suit.is_some()
+                && !card.wildcard
+                && cards
+                    .iter()
+                    .any(|other| other.rank == card.rank && other.suit == card.suit)
+            {
+                bail!("duplicate card {card} in hand {input}");
+            }
+            cards.push(card);
+        }
+        if cards.len() != self.hand_size {
+            bail!(
+                "hand {input} has {} cards, expected {}",
+                cards.len(),
+                self.hand_size
+            );
+        }
+        Ok(cards)
+    }
+
+    fn parse_hand(&self, input: &str) -> Result<Hand> {
+        let mut input = input.split_whitespace();
+        let cards = input.next().ok_or_else(|| anyhow!("missing hand"))?;
+        let cards = self.parse_cards(cards)?;
+        let bid_amount = input
+            .next()
+            .ok_or_else(|| anyhow!("missing bid"))?
+            .parse::<u32>()
+            .map_err(|e| anyhow!("invalid bid: {e}"))?;
+        if let Some(rest) = input.next() {
+            bail!("unexpected '{rest}' after the bid");
+        }
+
+        let hand_type = self.hand_type(&cards);
+        if self.suits.is_empty() {
+            return Ok(Hand::from_cards(cards, hand_type, bid_amount));
+        }
+        let ranks = self.poker_ranks(&cards, hand_type);
+        Ok(Hand::with_key_ranks(cards, hand_type, &ranks, bid_amount))
+    }
+
+    // the ranks in the order poker compares hands of the same type, wildcards count as the best card
+    // they can stand for
+    fn poker_ranks(&self, cards: &[Card], hand_type: HandType) -> Vec<u8> {
+        let mut ranks = cards
+            .iter()
+            .filter(|card| !card.wildcard)
+            .map(|card| card.rank)
+            .collect::<Vec<_>>();
+        let num_wildcards = cards.len() - ranks.len();
+
+        match hand_type {
+            // the hand type guarantees the straight
+            HandType::Straight | HandType::StraightFlush | HandType::RoyalFlush => {
+                vec![self.straight_top(cards).unwrap() as u8]
+            }
+            HandType::Flush | HandType::HighCard => {
+                // the cards of a flush all differ, so the wildcards become the highest missing ones
+                let missing = (0..self.alphabet.len())
+                    .rev()
+                    .filter(|rank| !self.wildcards.contains(&self.alphabet[*rank]))
+                    .map(|rank| rank as u8)
+                    .filter(|rank| !ranks.contains(rank))
+                    .take(num_wildcards)
+                    .collect::<Vec<_>>();
+                ranks.extend(missing);
+                ranks.sort_by(|a, b| b.cmp(a));
+                ranks
+            }
+            _ => {
+                let mut counts = HashMap::new();
+                for rank in ranks {
+                    *counts.entry(rank).or_insert(0) += 1;
+                }
+                let mut groups = counts.into_iter().collect::<Vec<(u8, usize)>>();
+                groups.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(b.cmp(a)));
+                // the wildcards join the best group, or make up a group of the highest card
+                match groups.first_mut() {
+                    Some((_, count)) => *count += num_wildcards,
+                    None => groups.push(((self.alphabet.len() - 1) as u8, num_wildcards)),
+                }
+                groups
+                    .into_iter()
+                    .flat_map(|(rank, count)| std::iter::repeat_n(rank, count))
+                    .collect()
+            }
+        }
+    }
+
+    fn is_flush(&self, cards: &[Card]) -> bool {
+        let mut suits = cards
+            .iter()
+            .filter(|card| !card.wildcard)
+            .map(|card| card.suit);
+        match suits.next() {
+            Some(suit) => suits.all(|other| other == suit),
+            None => true,
+        }
+    }
+
+    // position of the highest card of the best straight, wildcards fill the gaps
+    fn straight_top(&self, cards: &[Card]) -> Option<usize> {
+        let n = cards.len();
+        let length = self.sequence.len();
+        if n > length {
+            return None;
+        }
+        let mut positions = cards
+            .iter()
+            .filter(|card| !card.wildcard)
+            .map(|card| self.sequence.iter().position(|c| *c == card.symbol))
+            .collect::<Option<Vec<_>>>()?;
+        positions.sort();
+        if positions.windows(2).any(|pair| pair[0] == pair[1]) {
+            return None;
+        }
+
+        let (Some(&lowest), Some(&highest)) = (positions.first(), positions.last()) else {
+            // nothing but wildcards
+            return Some(length - 1);
+        };
+        if highest - lowest < n {
+            // slide the window as high as the sequence allows
+            return Some((lowest + n - 1).min(length - 1));
+        }
+
+        // the last card can also come before the first one, like A2345
+        let wrapped = positions
+            .iter()
+            .map(|position| (position + 1) % length)
+            .collect::<Vec<_>>();
+        let (lowest, highest) = (wrapped.iter().min()?, wrapped.iter().max()?);
+        (highest - lowest < n).then_some(n - 2)
+    }
+
+    fn hand_type(&self, cards: &[Card]) -> HandType {
+        let groups = HandType::new(cards);
+        if self.suits.is_empty() {
+            return groups;
+        }
+
+        // every type is the best the wildcards can make of it, so the best hand is the largest one
+        let straight = self.straight_top(cards);
+        let flush = self.is_flush(cards);
+        let poker = match (straight, flush) {
+            (Some(top), true) if top == self.sequence.len() - 1 => HandType::RoyalFlush,
+            (Some(_), true) => HandType::StraightFlush,
+            (None, true) => HandType::Flush,
+            (Some(_), false) => HandType::Straight,
+            (None, false) => HandType::HighCard,
+        };
+        groups.max(poker)
+    }
+
+    fn parse_hands(&self, input: &str) -> Result<Vec<Hand>> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                self.parse_hand(line)
+                    .map_err(|e| anyhow!("line {}: {}", i + 1, e))
+            })
+            .collect()
+    }
+
+    // the type the hand would have if the wildcards were ordinary cards
+    fn natural_hand_type(&self, cards: &[Card]) -> HandType {
+        let cards = cards
+            .iter()
+            .map(|card| Card {
+                wildcard: false,
+                ..*card
+            })
+            .collect::<Vec<_>>();
+        self.hand_type(&cards)
+    }
+
+    fn ranking_report(&self, input: &str) -> Result<RankingReport> {
+        let mut hands = self.parse_hands(input)?;
+        hands.sort();
+
+        let ranked = hands
+            .iter()
+            .enumerate()
+            .map(|(i, hand)| RankedHand {
+                rank: i + 1,
+                cards: hand.cards.iter().map(|card| card.to_string()).collect(),
+                hand_type: hand.hand_type,
+                natural_type: self.natural_hand_type(&hand.cards),
+                bid_amount: hand.bid_amount,
+                winnings: hand.bid_amount as u64 * (i as u64 + 1),
+            })
+            .collect::<Vec<_>>();
+
+        let mut histogram = BTreeMap::new();
+        for hand in &ranked {
+            *histogram.entry(hand.hand_type).or_insert(0) += 1;
+        }
+
+        // hands that compare equal, their order among each other is arbitrary
+        let ties = (0..hands.len())
+            .collect::<Vec<_>>()
+            .chunk_by(|a, b| hands[*a].cmp(&hands[*b]).is_eq())
+            .filter(|ranks| ranks.len() > 1)
+            .map(|ranks| ranks.iter().map(|i| i + 1).collect())
+            .collect();
+
+        Ok(RankingReport {
+            total: ranked.iter().map(|hand| hand.winnings).sum(),
+            hands: ranked,
+            histogram,
+            ties,
+        })
+    }
+
+    fn total_winning(&self, input: &str) -> Result<u64> {
+        let mut hands = self.parse_hands(input)?;
+        hands.sort();
+        Ok(hands
+            .iter()
+            .enumerate()
+            .map(|(rank, hand)| hand.bid_amount as u64 * (rank as u64 + 1))
+            .sum())
+    }
 }
 
-fn parse_cards_joker(input: &str) -> [CardType; 5] {
-    let cards = input
-        .chars()
-        .map(|c| CardType::from_char_joker(c).unwrap())
-        .collect::<Vec<_>>();
-    let cards = cards.as_slice();
-    let mut hand = [CardType::Ace; 5];
-    for i in 0..5 {
-        hand[i] = cards[i];
-    }
-    hand
+fn parse_cards(input: &str) -> Vec<Card> {
+    Ruleset::standard().parse_cards(input).unwrap()
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+// in poker precedence, camel cards never sees straights and flushes
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 enum HandType {
     HighCard,
     OnePair,
     TwoPairs,
     ThreeOfAKind,
+    Straight,
+    Flush,
     FullHouse,
     FourOfAKind,
+    StraightFlush,
+    RoyalFlush,
     FiveOfAKind,
 }
 
-impl HandType {
-    fn new(cards: [CardType; 5]) -> HandType {
-        let mut counts = HashMap::new();
-        for card in cards.iter() {
-            let count = counts.entry(card).or_insert(0);
-            *count += 1;
+// the sizes of the groups of equal cards, from the largest to the smallest, wildcards join the largest group
+fn group_counts(cards: &[Card]) -> Vec<usize> {
+    let mut counts = HashMap::new();
+    let mut num_jokers = 0;
+    for card in cards.iter() {
+        // jokers are added back to the largest group later
+        if card.wildcard {
+            num_jokers += 1;
+            continue;
         }
-        let num_jokers = counts.get(&CardType::Joker).unwrap_or(&0).clone();
-        // remove jokers
-        counts.remove(&CardType::Joker);
+        let count = counts.entry(card.rank).or_insert(0);
+        *count += 1;
+    }
 
-        let mut counts = counts.values().cloned().collect::<Vec<_>>();
-        // sort by count
-        counts.sort();
-        counts.reverse();
+    let mut counts = counts.values().cloned().collect::<Vec<_>>();
+    // sort by count
+    counts.sort();
+    counts.reverse();
 
-        // add jokers back
-        if counts.len() == 0 {
-            counts.push(0);
-        }
-        counts[0] += num_jokers;
+    // add jokers back
+    if counts.len() == 0 {
+        counts.push(0);
+    }
+    counts[0] += num_jokers;
+    counts
+}
 
-        match counts.as_slice() {
-            [5, ..] => HandType::FiveOfAKind,
+impl HandType {
+    // classifies hands of any size by their largest groups, five of a kind includes larger groups.
+    // Larger hands of the same type are told apart by all their group sizes in the sort key.
+    fn new(cards: &[Card]) -> HandType {
+        match group_counts(cards).as_slice() {
+            [n, ..] if *n >= 5 => HandType::FiveOfAKind,
             [4, ..] => HandType::FourOfAKind,
-            [3, 2, ..] => HandType::FullHouse,
+            [3, n, ..] if *n >= 2 => HandType::FullHouse,
             [3, ..] => HandType::ThreeOfAKind,
             [2, 2, ..] => HandType::TwoPairs,
             [2, ..] => HandType::OnePair,
@@ -134,101 +457,346 @@ impl HandType {
 
     fn from_str(input: &str) -> HandType {
         let hand = parse_cards(input);
-        HandType::new(hand)
-    }
-
-    fn from_str_joker(input: &str) -> HandType {
-        let hand = parse_cards_joker(input);
-        HandType::new(hand)
+        HandType::new(&hand)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Hand {
-    cards: [CardType; 5],
+    cards: Vec<Card>,
     bid_amount: u32,
+    hand_type: HandType,
+    // hand type in the top bits, then half a byte per group size (from the largest) and one byte per
+    // card rank, in the order they were dealt or in poker order
+    sort_key: u128,
 }
 
 impl Hand {
     fn new(cards: &str, bid_amount: u32) -> Hand {
+        let cards = parse_cards(cards);
+        let hand_type = HandType::new(&cards);
+        Hand::from_cards(cards, hand_type, bid_amount)
+    }
+
+    fn from_cards(cards: Vec<Card>, hand_type: HandType, bid_amount: u32) -> Hand {
+        let ranks = cards.iter().map(|card| card.rank).collect::<Vec<_>>();
+        Hand::with_key_ranks(cards, hand_type, &ranks, bid_amount)
+    }
+
+    // the ranks break ties between hands of the same type in the given order, not in the dealt one
+    fn with_key_ranks(
+        cards: Vec<Card>,
+        hand_type: HandType,
+        ranks: &[u8],
+        bid_amount: u32,
+    ) -> Hand {
+        // straights and flushes are not made of groups, only their ranks compare
+        let mut counts = match hand_type {
+            HandType::Straight
+            | HandType::Flush
+            | HandType::StraightFlush
+            | HandType::RoyalFlush => Vec::new(),
+            _ => group_counts(&cards),
+        };
+        counts.resize(cards.len(), 0);
+
+        let sort_key = counts
+            .iter()
+            .fold(hand_type as u128, |key, count| key << 4 | *count as u128);
+        // every hand of a ruleset has the same number of ranks in its key
+        let sort_key = (0..cards.len())
+            .map(|i| ranks.get(i).copied().unwrap_or(0))
+            .fold(sort_key, |key, rank| key << 8 | rank as u128);
         Hand {
-            cards: parse_cards(cards),
+            cards,
             bid_amount,
+            hand_type,
+            sort_key,
         }
     }
 
     fn get_hand_type(&self) -> HandType {
-        HandType::new(self.cards)
+        self.hand_type
+    }
+}
+
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sort_key.cmp(&other.sort_key)
+    }
+}
+
+impl PartialOrd for Hand {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn parse(input: &str) -> Hand {
-        let input = input.split_whitespace().collect::<Vec<_>>();
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RankedHand {
+    rank: usize,
+    cards: String,
+    hand_type: HandType,
+    // differs from hand_type where the wildcards promoted the hand
+    natural_type: HandType,
+    bid_amount: u32,
+    winnings: u64,
+}
 
-        Hand {
-            cards: parse_cards(input[0]),
-            bid_amount: input[1].parse::<u32>().unwrap(),
+impl RankedHand {
+    fn promoted(&self) -> bool {
+        self.hand_type != self.natural_type
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RankingReport {
+    hands: Vec<RankedHand>,
+    histogram: BTreeMap<HandType, usize>,
+    // ranks of hands with exactly the same strength
+    ties: Vec<Vec<usize>>,
+    total: u64,
+}
+
+type HandId = usize;
+
+// hands are ordered by their sort key, equal hands by their id, i.e. in the order they were inserted
+type RankKey = (u128, HandId);
+type RankLink = Option<Box<RankNode>>;
+
+#[derive(Debug, Clone)]
+struct RankNode {
+    key: RankKey,
+    priority: u64,
+    bid: u64,
+    // number of nodes and sum of their bids in this subtree
+    size: usize,
+    bids: u64,
+    left: RankLink,
+    right: RankLink,
+}
+
+fn subtree_size(link: &RankLink) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn subtree_bids(link: &RankLink) -> u64 {
+    link.as_ref().map_or(0, |node| node.bids)
+}
+
+impl RankNode {
+    fn new(key: RankKey, bid: u64) -> RankNode {
+        // splitmix64 of the id, a fixed pseudo random priority keeps the tree balanced in expectation
+        let mut priority = (key.1 as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        priority = (priority ^ (priority >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        priority = (priority ^ (priority >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        RankNode {
+            key,
+            priority: priority ^ (priority >> 31),
+            bid,
+            size: 1,
+            bids: bid,
+            left: None,
+            right: None,
         }
     }
 
-    fn parse_joker(input: &str) -> Hand {
-        let input = input.split_whitespace().collect::<Vec<_>>();
+    fn update(&mut self) {
+        self.size = 1 + subtree_size(&self.left) + subtree_size(&self.right);
+        self.bids = self.bid + subtree_bids(&self.left) + subtree_bids(&self.right);
+    }
+}
 
-        Hand {
-            cards: parse_cards_joker(input[0]),
-            bid_amount: input[1].parse::<u32>().unwrap(),
+// (keys smaller than key, the others)
+fn split(link: RankLink, key: RankKey) -> (RankLink, RankLink) {
+    match link {
+        None => (None, None),
+        Some(mut node) if node.key < key => {
+            let (left, right) = split(node.right.take(), key);
+            node.right = left;
+            node.update();
+            (Some(node), right)
+        }
+        Some(mut node) => {
+            let (left, right) = split(node.left.take(), key);
+            node.left = right;
+            node.update();
+            (left, Some(node))
         }
     }
 }
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let self_hand_type = self.get_hand_type();
-        let other_hand_type = other.get_hand_type();
-        if self_hand_type != other_hand_type {
-            return self_hand_type.cmp(&other_hand_type);
+// all keys of left have to be smaller than the ones of right
+fn merge(left: RankLink, right: RankLink) -> RankLink {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(right)) if left.priority > right.priority => {
+            left.right = merge(left.right.take(), Some(right));
+            left.update();
+            Some(left)
+        }
+        (left, Some(mut right)) => {
+            right.left = merge(left, right.left.take());
+            right.update();
+            Some(right)
         }
+    }
+}
+
+// the order of the hands in a treap, every subtree knows its size and the sum of its bids, so
+// positions and the bids above a position take O(log n) expected time
+#[derive(Debug, Clone, Default)]
+struct RankTree {
+    root: RankLink,
+}
+
+impl RankTree {
+    // returns the position of the new key and the sum of the bids above it
+    fn insert(&mut self, key: RankKey, bid: u64) -> (usize, u64) {
+        let (below, above) = split(self.root.take(), key);
+        let result = (subtree_size(&below), subtree_bids(&above));
+        self.root = merge(merge(below, Some(Box::new(RankNode::new(key, bid)))), above);
+        result
+    }
+
+    // returns the position the key had and the sum of the bids above it
+    fn remove(&mut self, key: RankKey) -> Option<(usize, u64)> {
+        let (below, rest) = split(self.root.take(), key);
+        let (found, above) = split(rest, (key.0, key.1 + 1));
+        let result = found.map(|_| (subtree_size(&below), subtree_bids(&above)));
+        self.root = merge(below, above);
+        result
+    }
 
-        // loop through cards and compare
-        for i in 0..5 {
-            if self.cards[i] != other.cards[i] {
-                return self.cards[i].cmp(&other.cards[i]);
+    fn position(&self, key: RankKey) -> Option<usize> {
+        let mut link = &self.root;
+        let mut position = 0;
+        while let Some(node) = link {
+            match key.cmp(&node.key) {
+                std::cmp::Ordering::Less => link = &node.left,
+                std::cmp::Ordering::Equal => return Some(position + subtree_size(&node.left)),
+                std::cmp::Ordering::Greater => {
+                    position += subtree_size(&node.left) + 1;
+                    link = &node.right;
+                }
             }
         }
+        None
+    }
 
-        std::cmp::Ordering::Equal
+    // the ids from the weakest to the strongest hand
+    fn ids(&self) -> Vec<HandId> {
+        let mut ids = Vec::new();
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        loop {
+            while let Some(node) = link {
+                stack.push(node);
+                link = &node.left;
+            }
+            let Some(node) = stack.pop() else {
+                return ids;
+            };
+            ids.push(node.key.1);
+            link = &node.right;
+        }
     }
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+// hands kept in rank order as they come in, with the total winnings always up to date. Inserting,
+// removing, ranking and updating a bid take O(log n) expected time, iterating takes O(n).
+#[derive(Debug, Clone)]
+struct HandBook {
+    ruleset: Ruleset,
+    hands: HashMap<HandId, Hand>,
+    ranks: RankTree,
+    next_id: HandId,
+    total: u64,
+}
+
+impl HandBook {
+    fn new(ruleset: Ruleset) -> HandBook {
+        HandBook {
+            ruleset,
+            hands: HashMap::new(),
+            ranks: RankTree::default(),
+            next_id: 0,
+            total: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.hands.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.hands.is_empty()
+    }
+
+    fn total_winning(&self) -> u64 {
+        self.total
+    }
+
+    fn key(&self, id: HandId) -> Option<RankKey> {
+        self.hands.get(&id).map(|hand| (hand.sort_key, id))
+    }
+
+    fn insert(&mut self, hand: Hand) -> HandId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // every hand above moves up one rank
+        let bid = hand.bid_amount as u64;
+        let (position, bids_above) = self.ranks.insert((hand.sort_key, id), bid);
+        self.total += bid * (position as u64 + 1) + bids_above;
+        self.hands.insert(id, hand);
+        id
+    }
+
+    fn insert_line(&mut self, line: &str) -> Result<HandId> {
+        let hand = self.ruleset.parse_hand(line)?;
+        Ok(self.insert(hand))
+    }
+
+    fn remove(&mut self, id: HandId) -> Option<Hand> {
+        let key = self.key(id)?;
+        let (position, bids_above) = self.ranks.remove(key)?;
+        let hand = self.hands.remove(&id)?;
+        self.total -= hand.bid_amount as u64 * (position as u64 + 1) + bids_above;
+        Some(hand)
+    }
+
+    // returns the old bid
+    fn update_bid(&mut self, id: HandId, bid_amount: u32) -> Option<u32> {
+        let key = self.key(id)?;
+        let (position, _) = self.ranks.remove(key)?;
+        self.ranks.insert(key, bid_amount as u64);
+
+        let rank = position as u64 + 1;
+        let hand = self.hands.get_mut(&id)?;
+        let old_bid = hand.bid_amount;
+        self.total = self.total - old_bid as u64 * rank + bid_amount as u64 * rank;
+        hand.bid_amount = bid_amount;
+        Some(old_bid)
+    }
+
+    fn rank(&self, id: HandId) -> Option<usize> {
+        self.ranks
+            .position(self.key(id)?)
+            .map(|position| position + 1)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Hand> {
+        self.ranks.ids().into_iter().map(move |id| &self.hands[&id])
     }
 }
 
-fn calculate_total_winning(input: &str) -> u32 {
-    let mut hands = input
-        .lines()
-        .map(|line| Hand::parse(line))
-        .collect::<Vec<_>>();
-    hands.sort();
-    hands
-        .iter()
-        .enumerate()
-        .map(|(rank, hand)| hand.bid_amount * (rank as u32 + 1))
-        .sum()
+fn calculate_total_winning(input: &str) -> Result<u64> {
+    Ruleset::standard().total_winning(input)
 }
 
-fn calculate_total_winning_joker(input: &str) -> u32 {
-    let mut hands = input
-        .lines()
-        .map(|line| Hand::parse_joker(line))
-        .collect::<Vec<_>>();
-    hands.sort();
-    hands
-        .iter()
-        .enumerate()
-        .map(|(rank, hand)| hand.bid_amount * (rank as u32 + 1))
-        .sum()
+fn calculate_total_winning_joker(input: &str) -> Result<u64> {
+    Ruleset::joker().total_winning(input)
 }
 
 #[cfg(test)]
@@ -238,15 +806,44 @@ mod tests {
     #[test]
     fn test_parse_cards() {
         let input = "KA538";
-        let expected = [
-            CardType::King,
-            CardType::Ace,
-            CardType::Five,
-            CardType::Three,
-            CardType::Eight,
-        ];
         let result = parse_cards(input);
-        assert_eq!(result, expected);
+        let symbols = result.iter().map(|card| card.symbol).collect::<Vec<_>>();
+        let ranks = result.iter().map(|card| card.rank).collect::<Vec<_>>();
+        assert_eq!(symbols, vec!['K', 'A', '5', '3', '8']);
+        assert_eq!(ranks, vec![11, 12, 3, 1, 6]);
+        assert!(result.iter().all(|card| !card.wildcard));
+    }
+
+    #[test]
+    fn test_ruleset() {
+        let standard = Ruleset::standard();
+        let joker = Ruleset::joker();
+        assert!(standard.card('J').unwrap() > standard.card('T').unwrap());
+        assert!(joker.card('J').unwrap() < joker.card('2').unwrap());
+        assert!(joker.card('J').unwrap().wildcard);
+        assert_eq!(standard.card('X'), None);
+
+        let joker_hand = |input| HandType::new(&joker.parse_cards(input).unwrap());
+        assert_eq!(joker_hand("JJJJJ"), HandType::FiveOfAKind);
+        assert_eq!(joker_hand("QJJQ2"), HandType::FourOfAKind);
+        assert_eq!(joker_hand("T55J5"), HandType::FourOfAKind);
+        assert_eq!(joker_hand("2345J"), HandType::OnePair);
+        assert_eq!(joker_hand("2233J"), HandType::FullHouse);
+        assert!(joker.parse_hand("JKKK2 1").unwrap() < joker.parse_hand("QQQQ2 1").unwrap());
+
+        // a made up variant: 1 is the strongest card and both 2 and 3 are wild
+        let variant = Ruleset::new("23456789TJQKA1", "23", 5).unwrap();
+        assert_eq!(
+            HandType::new(&variant.parse_cards("1A2K3").unwrap()),
+            HandType::ThreeOfAKind
+        );
+        assert!(variant.parse_hand("AAAA1 1").unwrap() < variant.parse_hand("1111A 1").unwrap());
+        assert_eq!(
+            variant
+                .total_winning("AAAA1 10\n1111A 1\n23456 100")
+                .unwrap(),
+            100 + 10 * 2 + 3
+        );
     }
 
     #[test]
@@ -275,32 +872,505 @@ mod tests {
         assert!(hand1 < hand2);
     }
 
+    #[test]
+    fn test_hand_sizes() {
+        let three = Ruleset::new("23456789TJQKA", "", 3).unwrap();
+        let hand_type = |input| HandType::new(&three.parse_cards(input).unwrap());
+        assert_eq!(hand_type("AAA"), HandType::ThreeOfAKind);
+        assert_eq!(hand_type("A2A"), HandType::OnePair);
+        assert_eq!(hand_type("A23"), HandType::HighCard);
+        assert_eq!(
+            three.total_winning("AKQ 1\n222 10\n2A2 100").unwrap(),
+            1 + 200 + 30
+        );
+
+        let seven = Ruleset::new("J23456789TQKA", "J", 7).unwrap();
+        let hand_type = |input| HandType::new(&seven.parse_cards(input).unwrap());
+        assert_eq!(hand_type("AAAAAAK"), HandType::FiveOfAKind);
+        assert_eq!(hand_type("AAAKKK2"), HandType::FullHouse);
+        assert_eq!(hand_type("AKQT98J"), HandType::OnePair);
+        assert_eq!(hand_type("AAKKQQJ"), HandType::FullHouse);
+        assert_eq!(hand_type("2345JJJ"), HandType::FourOfAKind);
+
+        // the whole groups decide between hands of the same type before the first card does
+        let hand = |input| seven.parse_hand(input).unwrap();
+        assert!(hand("KKKKKKK 1") > hand("AAAAAQQ 1"));
+        assert!(hand("2222222 1") > hand("AAAAAAK 1"));
+        assert!(hand("222333K 1") > hand("AAAKKQQ 1"));
+        assert!(hand("AAAAAQQ 1") > hand("AAAAAQ2 1"));
+        assert!(hand("AAAKKQQ 1") > hand("KKKAAQQ 1"));
+
+        let largest = Ruleset::new("23456789TJQKA", "", MAX_HAND_SIZE).unwrap();
+        let weak = largest.parse_hand("AAAAAKKKK2 1").unwrap();
+        let strong = largest.parse_hand("AAAAAKKKK3 1").unwrap();
+        assert!(weak < strong);
+        assert_eq!(strong.sort_key >> 120, HandType::FiveOfAKind as u128);
+    }
+
+    #[test]
+    fn test_ruleset_errors() {
+        let error = |result: Result<Ruleset>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(Ruleset::new("23452", "", 5)),
+            "card '2' appears twice in the alphabet"
+        );
+        assert_eq!(
+            error(Ruleset::new("2345", "J", 5)),
+            "wildcard 'J' is not in the alphabet"
+        );
+        assert_eq!(
+            error(Ruleset::new("2345", "", 0)),
+            "hands of 0 cards are not supported, use 1 to 10"
+        );
+        assert_eq!(
+            error(Ruleset::new("2345", "", 11)),
+            "hands of 11 cards are not supported, use 1 to 10"
+        );
+        let alphabet = (0..300).filter_map(char::from_u32).collect::<String>();
+        assert_eq!(
+            error(Ruleset::new(&alphabet, "", 5)),
+            "alphabet has 300 cards, at most 256 are supported"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input| {
+            Ruleset::standard()
+                .parse_hands(input)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("32T3K 765\nKK677"), "line 2: missing bid");
+        assert_eq!(
+            error("32T3K 765\n\nKK67 28"),
+            "line 3: hand KK67 has 4 cards, expected 5"
+        );
+        assert_eq!(
+            error("32T3K7 765"),
+            "line 1: hand 32T3K7 has 6 cards, expected 5"
+        );
+        assert_eq!(error("32T3X 765"), "line 1: unknown card 'X' in hand 32T3X");
+        assert_eq!(
+            error("32T3K 7a5"),
+            "line 1: invalid bid: invalid digit found in string"
+        );
+        assert_eq!(
+            error("32T3K -765"),
+            "line 1: invalid bid: invalid digit found in string"
+        );
+        assert_eq!(
+            error("32T3K 765 12"),
+            "line 1: unexpected '12' after the bid"
+        );
+        // blank lines are skipped
+        let hands = Ruleset::standard()
+            .parse_hands("\n32T3K 765\n  \n")
+            .unwrap();
+        assert_eq!(hands, vec![Hand::new("32T3K", 765)]);
+    }
+
+    #[test]
+    fn test_poker_hand_type() {
+        let poker = Ruleset::poker();
+        let hand_type = |input| poker.hand_type(&poker.parse_cards(input).unwrap());
+        assert_eq!(hand_type("KhAd2s3c4h"), HandType::HighCard);
+        assert_eq!(hand_type("QhQdQc2s3h"), HandType::ThreeOfAKind);
+        assert_eq!(hand_type("2h3d4s5c6h"), HandType::Straight);
+        assert_eq!(hand_type("Ah2d3s4c5h"), HandType::Straight);
+        assert_eq!(hand_type("2h7h9hJhKh"), HandType::Flush);
+        assert_eq!(hand_type("AhAdAcKsKh"), HandType::FullHouse);
+        assert_eq!(hand_type("AhAdAcAsKh"), HandType::FourOfAKind);
+        assert_eq!(hand_type("9hThJhQhKh"), HandType::StraightFlush);
+        assert_eq!(hand_type("Ah2h3h4h5h"), HandType::StraightFlush);
+        assert_eq!(hand_type("ThJhQhKhAh"), HandType::RoyalFlush);
+
+        // camel cards don't know straights and flushes
+        assert_eq!(HandType::from_str("23456"), HandType::HighCard);
+        assert_eq!(HandType::from_str("TJQKA"), HandType::HighCard);
+    }
+
+    #[test]
+    fn test_poker_wildcards() {
+        let poker = Ruleset::poker_joker();
+        let hand_type = |input| poker.hand_type(&poker.parse_cards(input).unwrap());
+        assert_eq!(hand_type("2h3dJs5c6h"), HandType::Straight);
+        assert_eq!(hand_type("9hJcJdQhKh"), HandType::StraightFlush);
+        assert_eq!(hand_type("Ah2hJc4h5h"), HandType::StraightFlush);
+        assert_eq!(hand_type("ThJcQhKhAh"), HandType::RoyalFlush);
+        assert_eq!(hand_type("QhJcJdKhAh"), HandType::RoyalFlush);
+        // the better of the possible hands wins
+        assert_eq!(hand_type("2h4h6h8hJh"), HandType::Flush);
+        assert_eq!(hand_type("2h2d3h3dJh"), HandType::FullHouse);
+        assert_eq!(hand_type("2h2d2cJh3h"), HandType::FourOfAKind);
+        assert_eq!(hand_type("AhAdJcAsAc"), HandType::FiveOfAKind);
+        assert_eq!(hand_type("JhJdJcJsJh"), HandType::FiveOfAKind);
+        // the jack is always a joker, so it doesn't close the gap between ten and queen
+        assert_eq!(hand_type("8h9dTsQcKh"), HandType::HighCard);
+    }
+
+    #[test]
+    fn test_poker_tournament() {
+        let input = "2h3d4s5c6h 10\nAhAdAcKsKh 1\n2h7h9hJhKh 100\nKhAd2s3c4h 1000";
+        // high card, straight, flush, full house
+        assert_eq!(
+            Ruleset::poker().total_winning(input).unwrap(),
+            1000 + 10 * 2 + 100 * 3 + 4
+        );
+        // camel cards: three high cards ordered card by card, full house
+        let input = input
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .filter(|c| !"cdhs".contains(*c))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            Ruleset::standard().total_winning(&input).unwrap(),
+            10 + 100 * 2 + 1000 * 3 + 4
+        );
+    }
+
+    #[test]
+    fn test_poker_ties() {
+        let poker = Ruleset::poker();
+        let hand = |input| poker.parse_hand(input).unwrap();
+        // the wheel is the lowest straight, even though it is dealt with the ace first
+        assert!(hand("Ah2d3s4c5h 1") < hand("2h3d4s5c6h 1"));
+        assert!(hand("Ah2h3h4h5h 1") < hand("2c3c4c5c6c 1"));
+        // the higher pair wins, wherever it was dealt
+        assert!(hand("4hKdKsQc3h 1") < hand("3hAdAsQc4h 1"));
+        assert!(hand("2h2d2cKsKh 1") < hand("KcKd3h3d3s 1"));
+        // then the kickers, from the highest
+        assert!(hand("AhAd8s7c6h 1") < hand("3hAsAc9d5s 1"));
+        assert!(hand("Ah9h5h3h2h 1") > hand("Kd2dQdJd9d 1"));
+        // the order of the cards doesn't matter
+        assert_eq!(hand("Kh9d5s3c2h 1").sort_key, hand("2h3c5s9dKh 1").sort_key);
+
+        // a wildcard joins the largest group and fills a flush with the highest missing card
+        let poker = Ruleset::poker_joker();
+        let hand = |input| poker.parse_hand(input).unwrap();
+        assert!(hand("KhKdKc9s8h 1") < hand("5hAdJc6sAh 1"));
+        assert!(hand("Kh9h5h3h2h 1") < hand("2d3dJd5d8d 1"));
+        assert!(hand("2d3dJd5d8d 1") < hand("Ah9h5h3h2h 1"));
+    }
+
+    #[test]
+    fn test_poker_errors() {
+        let error = |input| Ruleset::poker().parse_hands(input).unwrap_err().to_string();
+        assert_eq!(
+            error("AhKdQsJcT 1"),
+            "line 1: card 'T' in hand AhKdQsJcT has no suit"
+        );
+        assert_eq!(
+            error("AhKdQxJcTh 1"),
+            "line 1: unknown suit 'x' in hand AhKdQxJcTh"
+        );
+        assert_eq!(
+            error("AhKdQs 1"),
+            "line 1: hand AhKdQs has 3 cards, expected 5"
+        );
+        assert_eq!(
+            error("2h3d4s5c6h 1\nAhKdQsAhTc 1"),
+            "line 2: duplicate card Ah in hand AhKdQsAhTc"
+        );
+        // the same wildcard can be dealt more than once
+        assert!(Ruleset::poker_joker().parse_cards("JhJhAdAsAc").is_ok());
+
+        let error = |result: Result<Ruleset>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(Ruleset::standard().with_suits("hh", "23456789TJQKA")),
+            "suit 'h' appears twice"
+        );
+        assert_eq!(
+            error(Ruleset::standard().with_suits("cdhs", "23456789TQKA")),
+            "card 'J' is missing from the straight order"
+        );
+        assert_eq!(
+            error(Ruleset::standard().with_suits("", "23456789TJQKA")),
+            "a suited ruleset needs at least one suit"
+        );
+    }
+
+    #[test]
+    fn test_ranking_report() {
+        let input = include_str!("../input/small.txt");
+        let report = Ruleset::joker().ranking_report(input).unwrap();
+        assert_eq!(report.total, 5905);
+        assert_eq!(
+            report.hands[2],
+            RankedHand {
+                rank: 3,
+                cards: "T55J5".to_string(),
+                hand_type: HandType::FourOfAKind,
+                natural_type: HandType::ThreeOfAKind,
+                bid_amount: 684,
+                winnings: 684 * 3,
+            }
+        );
+        let promoted = report
+            .hands
+            .iter()
+            .filter(|hand| hand.promoted())
+            .map(|hand| hand.cards.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(promoted, vec!["T55J5", "QQQJA", "KTJJT"]);
+        assert_eq!(
+            report.histogram,
+            BTreeMap::from([
+                (HandType::OnePair, 1),
+                (HandType::TwoPairs, 1),
+                (HandType::FourOfAKind, 3)
+            ])
+        );
+        assert!(report.ties.is_empty());
+
+        let report = Ruleset::standard()
+            .ranking_report("32T3K 1\nKK677 2\n32T3K 3\nKK677 4\n32T3K 5")
+            .unwrap();
+        assert_eq!(report.ties, vec![vec![1, 2, 3], vec![4, 5]]);
+        assert!(report.hands.iter().all(|hand| !hand.promoted()));
+
+        // suits don't break ties, neither does the order of the cards
+        let report = Ruleset::poker()
+            .ranking_report("AhKd2s3c4h 1\n2h3dAsKc4s 2")
+            .unwrap();
+        assert_eq!(report.ties, vec![vec![1, 2]]);
+
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        let report = Ruleset::joker().ranking_report(&file).unwrap();
+        assert_eq!(report.total, 253362743);
+        assert_eq!(report.histogram.values().sum::<usize>(), report.hands.len());
+    }
+
+    #[test]
+    fn test_ranking_report_csv() {
+        let input = include_str!("../input/small.txt");
+        let report = Ruleset::joker().ranking_report(input).unwrap();
+        let csv = debug::report_to_csv(&report);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[0],
+            "rank,cards,type,natural_type,promoted,bid,winnings"
+        );
+        assert_eq!(lines[1], "1,32T3K,OnePair,OnePair,false,765,765");
+        assert_eq!(lines[5], "5,KTJJT,FourOfAKind,TwoPairs,true,220,1100");
+
+        let csv = debug::histogram_to_csv(&report);
+        assert_eq!(csv, "type,count\nOnePair,1\nTwoPairs,1\nFourOfAKind,3\n");
+
+        // symbols that need quoting in a custom alphabet
+        let ruleset = Ruleset::new("2,\"A", "", 2).unwrap();
+        let report = ruleset.ranking_report("2, 1\n\"A 2").unwrap();
+        let csv = debug::report_to_csv(&report);
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "1,\"2,\",HighCard,HighCard,false,1,1"
+        );
+        assert_eq!(
+            csv.lines().nth(2).unwrap(),
+            "2,\"\"\"A\",HighCard,HighCard,false,2,4"
+        );
+    }
+
+    #[test]
+    fn test_hand_book() {
+        let input = include_str!("../input/small.txt");
+        let mut book = HandBook::new(Ruleset::joker());
+        assert!(book.is_empty());
+        let ids = input
+            .lines()
+            .map(|line| book.insert_line(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(book.len(), 5);
+        assert_eq!(book.total_winning(), 5905);
+        assert_eq!(book.rank(ids[0]), Some(1));
+        assert_eq!(book.rank(ids[2]), Some(2));
+        assert_eq!(book.rank(ids[3]), Some(5));
+
+        // KTJJT 220 is the strongest hand
+        assert_eq!(book.update_bid(ids[3], 1000), Some(220));
+        assert_eq!(book.total_winning(), 5905 + (1000 - 220) * 5);
+
+        let removed = book.remove(ids[0]).unwrap();
+        assert_eq!(removed.bid_amount, 765);
+        assert_eq!(book.remove(ids[0]), None);
+        assert_eq!(book.update_bid(ids[0], 1), None);
+        assert_eq!(book.rank(ids[3]), Some(4));
+        let expected = book
+            .iter()
+            .enumerate()
+            .map(|(i, hand)| hand.bid_amount as u64 * (i as u64 + 1))
+            .sum::<u64>();
+        assert_eq!(book.total_winning(), expected);
+
+        assert!(book.insert_line("KK67 1").is_err());
+        assert_eq!(book.len(), 4);
+    }
+
+    #[test]
+    fn test_hand_book_matches_sorting() {
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        let lines = file.lines().collect::<Vec<_>>();
+        let mut book = HandBook::new(Ruleset::standard());
+        let mut ids = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            ids.push(book.insert_line(line).unwrap());
+            if i % 97 == 0 {
+                let input = lines[..=i].join("\n");
+                assert_eq!(
+                    book.total_winning(),
+                    calculate_total_winning(&input).unwrap()
+                );
+            }
+        }
+        assert_eq!(book.total_winning(), 253313241);
+
+        // take every other hand out again
+        for id in ids.iter().step_by(2) {
+            book.remove(*id).unwrap();
+        }
+        let input = lines.iter().skip(1).step_by(2).cloned().collect::<Vec<_>>();
+        assert_eq!(
+            book.total_winning(),
+            calculate_total_winning(&input.join("\n")).unwrap()
+        );
+
+        // new bids for the remaining hands keep the ranks
+        for id in ids.iter().skip(1).step_by(6) {
+            let rank = book.rank(*id);
+            book.update_bid(*id, *id as u32).unwrap();
+            assert_eq!(book.rank(*id), rank);
+        }
+        let expected = book
+            .iter()
+            .enumerate()
+            .map(|(i, hand)| hand.bid_amount as u64 * (i as u64 + 1))
+            .sum::<u64>();
+        assert_eq!(book.total_winning(), expected);
+        assert_eq!(book.iter().count(), book.len());
+
+        // equal hands keep the order they came in, like the stable sort
+        let mut book = HandBook::new(Ruleset::standard());
+        let first = book.insert_line("32T3K 1").unwrap();
+        let second = book.insert_line("32T3K 2").unwrap();
+        assert_eq!((book.rank(first), book.rank(second)), (Some(1), Some(2)));
+        assert_eq!(
+            book.total_winning(),
+            calculate_total_winning("32T3K 1\n32T3K 2").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hand_book_large_totals() {
+        // this already overflows u32
+        let mut book = HandBook::new(Ruleset::standard());
+        for line in ["23456 4294967295", "AAAAA 4294967295", "KKKKK 4294967295"] {
+            book.insert_line(line).unwrap();
+        }
+        assert_eq!(book.total_winning(), u32::MAX as u64 * 6);
+        assert_eq!(
+            Ruleset::standard()
+                .total_winning("23456 4294967295\nAAAAA 4294967295")
+                .unwrap(),
+            u32::MAX as u64 * 3
+        );
+    }
+
+    #[test]
+    fn test_sort_key() {
+        let hand = Hand::new("KA538", 1);
+        // type, five groups of one card, then the ranks of K, A, 5, 3 and 8
+        let expected = (HandType::HighCard as u128) << 60 | 0x11111 << 40 | 0x0b_0c_03_01_06;
+        assert_eq!(hand.sort_key, expected);
+        let hand = Hand::new("AAAAA", 1);
+        assert_eq!(hand.sort_key >> 60, HandType::FiveOfAKind as u128);
+        assert_eq!((hand.sort_key >> 40) & 0xfffff, 0x50000);
+
+        // the key orders hands exactly like comparing type and then card by card
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        for ruleset in [Ruleset::standard(), Ruleset::joker()] {
+            let hands = file
+                .lines()
+                .map(|line| ruleset.parse_hand(line).unwrap())
+                .collect::<Vec<_>>();
+            for (a, b) in hands.iter().zip(hands.iter().skip(1)) {
+                let expected = HandType::new(&a.cards)
+                    .cmp(&HandType::new(&b.cards))
+                    .then(a.cards.cmp(&b.cards));
+                assert_eq!(a.cmp(b), expected);
+            }
+        }
+    }
+
+    // cargo test --release -p advent_of_code_day_7 -- --ignored --nocapture bench_sort
+    #[test]
+    #[ignore]
+    fn bench_sort_large_input() {
+        use std::time::Instant;
+
+        let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
+        let ruleset = Ruleset::joker();
+        let iterations = 200;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let mut hands = file
+                .lines()
+                .map(|line| ruleset.parse_hand(line).unwrap())
+                .collect::<Vec<_>>();
+            // the old comparison, classifying both hands on every call
+            hands.sort_by(|a, b| {
+                HandType::new(&a.cards)
+                    .cmp(&HandType::new(&b.cards))
+                    .then(a.cards.cmp(&b.cards))
+            });
+        }
+        let classify_on_compare = start.elapsed() / iterations;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let mut hands = file
+                .lines()
+                .map(|line| ruleset.parse_hand(line).unwrap())
+                .collect::<Vec<_>>();
+            hands.sort();
+        }
+        let sort_key = start.elapsed() / iterations;
+
+        println!("classify on compare: {classify_on_compare:?} per sort");
+        println!("precomputed sort key: {sort_key:?} per sort");
+    }
+
     #[test]
     fn small_input() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/small.txt");
-        assert_eq!(calculate_total_winning(input), 6440)
+        assert_eq!(calculate_total_winning(input).unwrap(), 6440)
     }
 
     #[test]
     fn large_input() {
         // You can also read the file completely into memory
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
-        assert_eq!(calculate_total_winning(&file), 253313241)
+        assert_eq!(calculate_total_winning(&file).unwrap(), 253313241)
     }
 
     #[test]
     fn small_input_joker() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/small.txt");
-        assert_eq!(calculate_total_winning_joker(input), 5905)
+        assert_eq!(calculate_total_winning_joker(input).unwrap(), 5905)
     }
 
     #[test]
     fn large_input_joker() {
         // You can also read the file completely into memory
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
-        assert_eq!(calculate_total_winning_joker(&file), 253362743)
+        assert_eq!(calculate_total_winning_joker(&file).unwrap(), 253362743)
     }
     // wrong answers:
     // 252727006
@@ -1,16 +1,18 @@
-use std::{collections::HashMap, vec};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
 
 use nom::{
     bytes::complete::tag,
-    character::complete::{alpha1, char, line_ending, anychar, one_of, alphanumeric1},
-    combinator::map,
+    character::complete::{char, line_ending, one_of, alphanumeric1, multispace0},
+    combinator::{all_consuming, map},
     multi::{separated_list1, many1},
-    sequence::{delimited, separated_pair, pair},
+    sequence::{delimited, separated_pair, pair, terminated},
     IResult,
 };
 
 mod debug;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Instruction {
     Left,
     Right,
@@ -25,15 +27,16 @@ fn parse_next_elements(input: &str) -> IResult<&str, (&str, &str)> {
     )(input)
 }
 
+// node name with its left and right successor
+type MapEntry<'a> = (&'a str, (&'a str, &'a str));
+
 //AAA = (BBB, BBB)
-fn parse_map_entry(input: &str) -> IResult<&str, (&str, (&str, &str))> {
+fn parse_map_entry(input: &str) -> IResult<&str, MapEntry<'_>> {
     separated_pair(alphanumeric1, tag(" = "), parse_next_elements)(input)
 }
 
-fn parse_map(input: &str) -> IResult<&str, HashMap<&str, (&str, &str)>> {
-    map(separated_list1(line_ending, parse_map_entry), |v| {
-        v.into_iter().collect()
-    })(input)
+fn parse_map(input: &str) -> IResult<&str, Vec<MapEntry<'_>>> {
+    separated_list1(line_ending, parse_map_entry)(input)
 }
 
 fn parse_instruction(input: &str) -> IResult<&str, Instruction> {
@@ -52,106 +55,473 @@ fn parse_empty_line(input: &str) -> IResult<&str, (&str, &str)> {
     pair(line_ending, line_ending)(input)
 }
 
-fn parse_input(input: &str) -> IResult<&str, (Vec<Instruction>, HashMap<&str, (&str, &str)>)> {
+fn parse_input(input: &str) -> IResult<&str, (Vec<Instruction>, Vec<MapEntry<'_>>)> {
     separated_pair(parse_instructions, parse_empty_line, parse_map)(input)
 }
 
-fn find_nr_steps_to_end(input: &str) -> usize {
-    let (_, (instructions, map)) = parse_input(input).unwrap();
-    
-    let mut nr_steps = 0;
-    let mut current = "AAA";
-    while current != "ZZZ" {
-        let (left, right) = map.get(current).unwrap();
-        current = match instructions[nr_steps % instructions.len()] {
+type NodeId = usize;
+
+// the network with every node replaced by its index
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Network<'a> {
+    names: Vec<&'a str>,
+    // left and right successor of every node
+    successors: Vec<(NodeId, NodeId)>,
+    // whether a ghost walk ends on the node, i.e. its name ends with Z
+    is_end: Vec<bool>,
+    ids: HashMap<&'a str, NodeId>,
+}
+
+impl<'a> Network<'a> {
+    fn new(entries: &[MapEntry<'a>]) -> Result<Network<'a>> {
+        let mut ids = HashMap::new();
+        for (id, (name, _)) in entries.iter().enumerate() {
+            if ids.insert(*name, id).is_some() {
+                bail!("node {name} is defined twice");
+            }
+        }
+
+        let lookup = |from: &str, to: &str| {
+            ids.get(to)
+                .copied()
+                .ok_or_else(|| anyhow!("node {from} leads to {to}, which has no entry"))
+        };
+        let successors = entries
+            .iter()
+            .map(|(name, (left, right))| Ok((lookup(name, left)?, lookup(name, right)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Network {
+            names: entries.iter().map(|(name, _)| *name).collect(),
+            successors,
+            is_end: entries.iter().map(|(name, _)| name.ends_with('Z')).collect(),
+            ids,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn id(&self, name: &str) -> Option<NodeId> {
+        self.ids.get(name).copied()
+    }
+
+    fn name(&self, node: NodeId) -> &'a str {
+        self.names[node]
+    }
+
+    fn is_end(&self, node: NodeId) -> bool {
+        self.is_end[node]
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        0..self.len()
+    }
+
+    fn next(&self, node: NodeId, instruction: &Instruction) -> NodeId {
+        let (left, right) = self.successors[node];
+        match instruction {
             Instruction::Left => left,
             Instruction::Right => right,
-        };
+        }
+    }
+}
+
+fn parse_network(input: &str) -> Result<(Vec<Instruction>, Network<'_>)> {
+    // a malformed line must not silently end the list of nodes
+    let (_, (instructions, entries)) = all_consuming(terminated(parse_input, multispace0))(input)
+        .map_err(|e| anyhow!("could not parse the network: {}", e.to_owned()))?;
+    Ok((instructions, Network::new(&entries)?))
+}
+
+fn find_nr_steps_to_end(input: &str) -> Result<Option<u64>> {
+    let (instructions, network) = parse_network(input)?;
+    let start = network
+        .id("AAA")
+        .ok_or_else(|| anyhow!("there is no node AAA"))?;
+    let end = network
+        .id("ZZZ")
+        .ok_or_else(|| anyhow!("there is no node ZZZ"))?;
+
+    // once every (node, instruction index) state is used up, the walk only repeats itself
+    let limit = (network.len() * instructions.len()) as u64;
+    let mut nr_steps = 0u64;
+    let mut current = start;
+    while current != end {
+        if nr_steps >= limit {
+            return Ok(None);
+        }
+        let index = (nr_steps % instructions.len() as u64) as usize;
+        current = network.next(current, &instructions[index]);
         nr_steps += 1;
     }
-    nr_steps
+    Ok(Some(nr_steps))
+}
+
+// the steps at which one ghost stands on an end node
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GhostCycle {
+    // hits before the ghost enters its cycle, they only happen once
+    prefix_hits: Vec<u128>,
+    // from here on the walk repeats every period steps
+    cycle_start: u128,
+    period: u128,
+    // hits during the first pass through the cycle
+    cycle_hits: Vec<u128>,
+}
+
+impl GhostCycle {
+    fn contains(&self, step: u128) -> bool {
+        if step < self.cycle_start {
+            return self.prefix_hits.contains(&step);
+        }
+        let offset = (step - self.cycle_start) % self.period;
+        self.cycle_hits
+            .iter()
+            .any(|hit| hit - self.cycle_start == offset)
+    }
+}
+
+// walks until a (node, instruction index) state repeats, that's where the cycle closes
+fn find_ghost_cycle(instructions: &[Instruction], network: &Network, start: NodeId) -> GhostCycle {
+    // first step of every (node, instruction index) state
+    let mut visited = vec![None; network.len() * instructions.len()];
+    let mut hits = Vec::new();
+    let mut current = start;
+    let mut step = 0u128;
+    loop {
+        let index = (step % instructions.len() as u128) as usize;
+        let state = current * instructions.len() + index;
+        if let Some(first) = visited[state] {
+            let (prefix_hits, cycle_hits) = hits.iter().partition(|hit| **hit < first);
+            return GhostCycle {
+                prefix_hits,
+                cycle_start: first,
+                period: step - first,
+                cycle_hits,
+            };
+        }
+        visited[state] = Some(step);
+        if network.is_end(current) {
+            hits.push(step);
+        }
+
+        current = network.next(current, &instructions[index]);
+        step += 1;
+    }
 }
 
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
 
-//I think the input is carefully constructed in such a way that it works. There are multiple interesting things about the input:
-// Each start (xxA) leads to one of the targets (yyZ) in a one-to-one fashion
-// Each targets then loops onto itself (e.g. LQZ -> LQZ)
-// From start (xxA) to target (yyZ) and from target to target (the same target, see above) you always do a full number of loops through the left/right instructions, never a fraction thereof.
-// The path lengths start -> target and target -> target are the same in every "row", so one can treat start -> target in the beginning as just another loop. That is, you don't start off the target -> target loop at a fraction of the instruction loop. (If you did, every loop would start with a different offset, making this orders of magnitude more complicated!)
-// The path lengths target -> target are all prime, so the lowest common denominator reduces to taking the product.
-fn find_nr_steps_to_end_complicated(input: &str) -> u128 {
-    let (_, (instructions, map)) = parse_input(input).unwrap();
+// inverse of a modulo m, a and m have to be coprime
+fn mod_inverse(a: u128, m: u128) -> u128 {
+    let (mut old_r, mut r) = (a as i128, m as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    old_s.rem_euclid(m as i128) as u128
+}
 
-    println!("instruction length: {}", instructions.len());
+// x = r1 (mod m1) and x = r2 (mod m2) => x = r (mod lcm(m1, m2)), the moduli don't need to be coprime
+fn combine_congruences(
+    (r1, m1): (u128, u128),
+    (r2, m2): (u128, u128),
+) -> Result<Option<(u128, u128)>> {
+    let g = gcd(m1, m2);
+    let difference = (r2 % m2 + m2 - r1 % m2) % m2;
+    if difference % g != 0 {
+        return Ok(None);
+    }
 
-    let mut starts = Vec::new();
-    map.keys().filter(|k| k.ends_with('A')).for_each(|k| starts.push(k));
+    // x = r1 + m1 * k with m1 * k = difference (mod m2)
+    let n2 = m2 / g;
+    let k = (difference / g) % n2 * mod_inverse(m1 / g % n2, n2) % n2;
+    let lcm = m1
+        .checked_mul(n2)
+        .ok_or_else(|| anyhow!("the ghosts only meet after more than {} steps", u128::MAX))?;
+    Ok(Some(((r1 % m1 + m1 * k) % lcm, lcm)))
+}
 
-    println!("Starts: {:?}", starts);
+fn first_common_step(cycles: &[GhostCycle]) -> Result<Option<u128>> {
+    let Some(latest) = cycles.iter().max_by_key(|cycle| cycle.cycle_start) else {
+        // nobody to wait for
+        return Ok(Some(0));
+    };
 
-    let nr_steps = starts.iter().map(|start| {
-        println!("Start: {}", start);
+    // before every ghost is in its cycle, only the prefix hits of the last one to get there count
+    if let Some(step) = latest
+        .prefix_hits
+        .iter()
+        .find(|step| cycles.iter().all(|cycle| cycle.contains(**step)))
+    {
+        return Ok(Some(*step));
+    }
 
-        let mut nr_steps = 0u128;
-        let mut current = *start;
-        while !current.ends_with('Z') {
-            let (left, right) = map.get(current).unwrap();
-            current = match instructions[nr_steps as usize % instructions.len()] {
-                Instruction::Left => left,
-                Instruction::Right => right,
-            };
-            nr_steps += 1;
+    // afterwards every ghost has to be at one of its cycle hits, modulo its period
+    let mut congruences = vec![(0u128, 1u128)];
+    for cycle in cycles {
+        let mut next = Vec::new();
+        for congruence in &congruences {
+            for hit in &cycle.cycle_hits {
+                let hit = (hit % cycle.period, cycle.period);
+                if let Some(combined) = combine_congruences(*congruence, hit)? {
+                    next.push(combined);
+                }
+            }
         }
-        nr_steps
-       
-    }).collect::<Vec<_>>();
-        
-    println!("Steps: {:?}", nr_steps);
-    println!("instruction loops: {:?}", nr_steps.iter().map(|x| *x as f32 / instructions.len() as f32).collect::<Vec<_>>());
+        next.sort();
+        next.dedup();
+        congruences = next;
+    }
 
-    let nr_steps = nr_steps.iter().map(|x| *x / instructions.len() as u128).collect::<Vec<_>>();
+    // the first step at or after the start of the last cycle
+    let start = latest.cycle_start;
+    congruences
+        .iter()
+        .map(|(r, m)| {
+            let behind = start.saturating_sub(*r);
+            behind
+                .div_ceil(*m)
+                .checked_mul(*m)
+                .and_then(|jump| jump.checked_add(*r))
+                .ok_or_else(|| anyhow!("the ghosts only meet after more than {} steps", u128::MAX))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|steps| steps.into_iter().min())
+}
+
+// every ghost starts on a node ending in A, all of them have to stand on a node ending in Z at once
+fn find_nr_steps_to_end_complicated(input: &str) -> Result<Option<u128>> {
+    let (instructions, network) = parse_network(input)?;
+
+    let cycles = network
+        .nodes()
+        .filter(|node| network.name(*node).ends_with('A'))
+        .map(|start| find_ghost_cycle(&instructions, &network, start))
+        .collect::<Vec<_>>();
+    if cycles.is_empty() {
+        bail!("there are no start nodes ending in A");
+    }
+    log::debug!("ghost cycles: {:?}", cycles);
 
-    nr_steps.iter().product::<u128>() * instructions.len() as u128 // LCM (works in this case, because the numbers are prime)
+    first_common_step(&cycles)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_network() {
+        let input = include_str!("../input/small.txt");
+        let (instructions, network) = parse_network(input).unwrap();
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(network.len(), 3);
+        assert!(!network.is_empty());
+        let (aaa, bbb, zzz) = (
+            network.id("AAA").unwrap(),
+            network.id("BBB").unwrap(),
+            network.id("ZZZ").unwrap(),
+        );
+        assert_eq!(network.name(bbb), "BBB");
+        assert_eq!(network.next(aaa, &Instruction::Left), bbb);
+        assert_eq!(network.next(bbb, &Instruction::Right), zzz);
+        assert_eq!(network.id("CCC"), None);
+    }
+
+    #[test]
+    fn test_network_errors() {
+        let error = |input| parse_network(input).unwrap_err().to_string();
+        assert_eq!(
+            error("LR\n\nAAA = (BBB, CCC)\nBBB = (AAA, AAA)"),
+            "node AAA leads to CCC, which has no entry"
+        );
+        assert_eq!(
+            error("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)"),
+            "node AAA is defined twice"
+        );
+        assert!(error("LX\n\nAAA = (AAA, AAA)").starts_with("could not parse the network"));
+        // the nodes after a malformed line are not dropped
+        assert!(error("L\n\nAAA = (ZZZ, ZZZ)\nBBB = (AAA ZZZ)\nZZZ = (ZZZ, ZZZ)")
+            .starts_with("could not parse the network"));
+        assert!(parse_network("L\n\nAAA = (AAA, AAA)\n\n").is_ok());
+
+        let error = |input| find_nr_steps_to_end(input).unwrap_err().to_string();
+        assert_eq!(error("L\n\nAAA = (AAA, AAA)"), "there is no node ZZZ");
+        // ZZZ exists, but can't be reached
+        assert_eq!(
+            find_nr_steps_to_end("L\n\nAAA = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_combine_congruences() {
+        assert_eq!(combine_congruences((2, 3), (3, 5)).unwrap(), Some((8, 15)));
+        // moduli sharing a factor
+        assert_eq!(combine_congruences((1, 4), (3, 6)).unwrap(), Some((9, 12)));
+        assert_eq!(combine_congruences((0, 4), (1, 6)).unwrap(), None);
+        assert_eq!(combine_congruences((5, 7), (5, 7)).unwrap(), Some((5, 7)));
+        assert!(combine_congruences((0, u128::MAX), (0, 2)).is_err());
+    }
+
+    #[test]
+    fn test_ghost_cycle() {
+        let input = include_str!("../input/second_example.txt");
+        let (instructions, network) = parse_network(input).unwrap();
+        assert_eq!(
+            find_ghost_cycle(&instructions, &network, network.id("11A").unwrap()),
+            GhostCycle {
+                prefix_hits: vec![],
+                cycle_start: 1,
+                period: 2,
+                cycle_hits: vec![2],
+            }
+        );
+        assert_eq!(
+            find_ghost_cycle(&instructions, &network, network.id("22A").unwrap()),
+            GhostCycle {
+                prefix_hits: vec![],
+                cycle_start: 1,
+                period: 6,
+                cycle_hits: vec![3, 6],
+            }
+        );
+    }
+
+    #[test]
+    fn test_ghost_walk_edge_cases() {
+        // 11A passes its only Z once before it gets stuck, 22A hits Z on every other step
+        let input = "L\n\n11A = (11Z, 11Z)\n11Z = (11B, 11B)\n11B = (11B, 11B)\n22A = (22Z, 22Z)\n22Z = (22A, 22A)";
+        assert_eq!(find_nr_steps_to_end_complicated(input).unwrap(), Some(1));
+        let input = "L\n\n11A = (11B, 11B)\n11B = (11Z, 11Z)\n11Z = (11C, 11C)\n11C = (11C, 11C)\n22A = (22Z, 22Z)\n22Z = (22A, 22A)";
+        assert_eq!(find_nr_steps_to_end_complicated(input).unwrap(), None);
+        // a ghost that starts on an end node
+        let input = "L\n\n11A = (11Z, 11Z)\n11Z = (11A, 11A)\n22A = (22Z, 22Z)\n22Z = (22B, 22B)\n22B = (22A, 22A)";
+        assert_eq!(find_nr_steps_to_end_complicated(input).unwrap(), Some(1));
+        // only the cycles, periods 4 and 6, which share a factor
+        let input = "L\n\n11A = (11B, 11B)\n11B = (11C, 11C)\n11C = (11Z, 11Z)\n11Z = (11A, 11A)\n22A = (22B, 22B)\n22B = (22C, 22C)\n22C = (22D, 22D)\n22D = (22E, 22E)\n22E = (22Z, 22Z)\n22Z = (22A, 22A)";
+        assert_eq!(find_nr_steps_to_end_complicated(input).unwrap(), Some(11));
+    }
+
+    // step all ghosts at once until they all stand on an end node
+    fn brute_force_ghost_walk(input: &str, limit: u128) -> Option<u128> {
+        let (instructions, network) = parse_network(input).unwrap();
+        let mut ghosts = network
+            .nodes()
+            .filter(|node| network.name(*node).ends_with('A'))
+            .collect::<Vec<_>>();
+        for step in 0..limit {
+            if ghosts.iter().all(|node| network.name(*node).ends_with('Z')) {
+                return Some(step);
+            }
+            for ghost in ghosts.iter_mut() {
+                *ghost = network.next(*ghost, &instructions[step as usize % instructions.len()]);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_ghost_walk_brute_force() {
+        // small random networks from a fixed seed
+        let mut seed = 0x2023_1208u64;
+        let mut random = |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let limit = 10_000;
+        for _ in 0..300 {
+            let nr_nodes = 2 + random(10);
+            let names = (0..nr_nodes)
+                .map(|i| {
+                    let suffix = match random(4) {
+                        0 => 'A',
+                        1 => 'Z',
+                        _ => 'X',
+                    };
+                    format!("{:02}{}", i, suffix)
+                })
+                .collect::<Vec<_>>();
+            let instructions = (0..1 + random(5))
+                .map(|_| if random(2) == 0 { 'L' } else { 'R' })
+                .collect::<String>();
+            let entries = names
+                .iter()
+                .map(|name| {
+                    let left = &names[random(nr_nodes) as usize];
+                    let right = &names[random(nr_nodes) as usize];
+                    format!("{} = ({}, {})", name, left, right)
+                })
+                .collect::<Vec<_>>();
+            let input = format!("{}\n\n{}", instructions, entries.join("\n"));
+
+            if !names.iter().any(|name| name.ends_with('A')) {
+                assert!(find_nr_steps_to_end_complicated(&input).is_err());
+                continue;
+            }
+
+            let expected = brute_force_ghost_walk(&input, limit);
+            let result = find_nr_steps_to_end_complicated(&input).unwrap();
+            match expected {
+                Some(_) => assert_eq!(result, expected, "{}", input),
+                None => assert!(result.is_none_or(|step| step >= limit), "{}", input),
+            }
+        }
+    }
+
     #[test]
     fn example_input() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/example.txt");
-        assert_eq!(find_nr_steps_to_end(input), 2)
+        assert_eq!(find_nr_steps_to_end(input).unwrap(), Some(2))
     }
 
     #[test]
     fn small_input() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/small.txt");
-        assert_eq!(find_nr_steps_to_end(input), 6)
+        assert_eq!(find_nr_steps_to_end(input).unwrap(), Some(6))
     }
 
     #[test]
     fn large_input() {
         // You can also read the file completely into memory
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
-        assert_eq!(find_nr_steps_to_end(&file), 13771)
+        assert_eq!(find_nr_steps_to_end(&file).unwrap(), Some(13771))
     }
 
-    #[ignore = "Does not work anymore with the higly specialized solution for the large input"]
     #[test]
     fn small_input_second_example() {
         // The easiest way to open the data is to include it into the generated binary.
         let input = include_str!("../input/second_example.txt");
-        assert_eq!(find_nr_steps_to_end_complicated(input), 6)
+        assert_eq!(find_nr_steps_to_end_complicated(input).unwrap(), Some(6))
     }
 
     #[test]
     fn large_input_complicated() {
         // You can also read the file completely into memory
         let file = std::fs::read_to_string("input/big.txt").expect("Could not open input file");
-        assert_eq!(find_nr_steps_to_end_complicated(&file), 13129439557681)
+        assert_eq!(
+            find_nr_steps_to_end_complicated(&file).unwrap(),
+            Some(13129439557681)
+        )
     }
     // wrong answers:
     // 28352038502929006347476933
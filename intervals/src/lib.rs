@@ -0,0 +1,405 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+
+// closed interval start..=end of u64 numbers, start <= end. The constructors check it, intervals built
+// from the fields directly are only checked in debug builds.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Interval {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Interval {
+    // closed interval start..=end, None if it would be empty
+    pub fn inclusive(start: u64, end: u64) -> Option<Interval> {
+        if start <= end {
+            Some(Interval { start, end })
+        } else {
+            None
+        }
+    }
+
+    // half-open interval start..end, None if it would be empty
+    pub fn half_open(start: u64, end: u64) -> Option<Interval> {
+        if start < end {
+            Some(Interval { start, end: end - 1 })
+        } else {
+            None
+        }
+    }
+
+    // interval of length numbers beginning at start, None if it would be empty or exceed u64::MAX
+    pub fn with_length(start: u64, length: u64) -> Option<Interval> {
+        let end = start.checked_add(length.checked_sub(1)?)?;
+        Some(Interval { start, end })
+    }
+
+    // number of contained numbers (u128 because 0..=u64::MAX has 2^64 of them)
+    pub fn size(&self) -> u128 {
+        debug_assert!(self.start <= self.end, "empty interval {:?}", self);
+        (self.end - self.start) as u128 + 1
+    }
+
+    pub fn contains(&self, number: &u64) -> bool {
+        *number >= self.start && *number <= self.end
+    }
+
+    pub fn overlap(&self, other: &Interval) -> Option<Interval> {
+        if self.start <= other.end && self.end >= other.start {
+            Some(Interval {
+                start: self.start.max(other.start),
+                end: self.end.min(other.end),
+            })
+        } else {
+            None
+        }
+    }
+
+    // the (up to two) parts of self which are not covered by other
+    pub fn difference(&self, other: &Interval) -> Vec<Interval> {
+        let mut difference = Vec::new();
+        if self.start < other.start {
+            difference.push(Interval {
+                start: self.start,
+                end: self.end.min(other.start - 1),
+            });
+        }
+        if self.end > other.end {
+            difference.push(Interval {
+                start: self.start.max(other.end + 1),
+                end: self.end,
+            });
+        }
+        difference
+    }
+
+    // true if both intervals overlap or are directly next to each other, i.e. can be merged into one
+    pub fn touches(&self, other: &Interval) -> bool {
+        self.start <= other.end.saturating_add(1) && other.start <= self.end.saturating_add(1)
+    }
+}
+
+impl Debug for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({}; {})", self.start, self.end)
+    }
+}
+
+// set of numbers stored as sorted, disjoint and non-adjacent intervals
+#[derive(PartialEq, Eq, Hash, Clone, Default)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    // total number of contained numbers
+    pub fn len(&self) -> u128 {
+        self.intervals.iter().map(Interval::size).sum()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Interval> {
+        self.intervals.iter()
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.intervals.first().map(|interval| interval.start)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.intervals.last().map(|interval| interval.end)
+    }
+
+    pub fn contains(&self, number: &u64) -> bool {
+        // index of the first interval which ends at or after number
+        let i = self.intervals.partition_point(|interval| interval.end < *number);
+        self.intervals
+            .get(i)
+            .is_some_and(|interval| interval.contains(number))
+    }
+
+    // insert interval and coalesce it with all intervals it overlaps or touches
+    pub fn insert(&mut self, interval: Interval) {
+        debug_assert!(interval.start <= interval.end, "empty interval {:?}", interval);
+        let first = self
+            .intervals
+            .partition_point(|other| other.end.saturating_add(1) < interval.start);
+        let last = self
+            .intervals
+            .partition_point(|other| other.start <= interval.end.saturating_add(1));
+
+        let mut merged = interval;
+        if first < last {
+            merged.start = merged.start.min(self.intervals[first].start);
+            merged.end = merged.end.max(self.intervals[last - 1].end);
+        }
+
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    // remove all numbers of interval from the set
+    pub fn remove(&mut self, interval: &Interval) {
+        let first = self
+            .intervals
+            .partition_point(|other| other.end < interval.start);
+        let last = self
+            .intervals
+            .partition_point(|other| other.start <= interval.end);
+
+        let remaining = self.intervals[first..last]
+            .iter()
+            .flat_map(|other| other.difference(interval))
+            .collect::<Vec<_>>();
+
+        self.intervals.splice(first..last, remaining);
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut union = self.clone();
+        other.iter().for_each(|interval| union.insert(*interval));
+        union
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        // both lists are sorted, so walk them in lockstep
+        let mut intersection = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (self.intervals[i], other.intervals[j]);
+            if let Some(overlap) = a.overlap(&b) {
+                intersection.push(overlap);
+            }
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet {
+            intervals: intersection,
+        }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut difference = self.clone();
+        other.iter().for_each(|interval| difference.remove(interval));
+        difference
+    }
+
+    // all numbers within bound which are not in the set
+    pub fn complement(&self, bound: &Interval) -> IntervalSet {
+        let mut complement = IntervalSet::from(*bound);
+        self.iter().for_each(|interval| complement.remove(interval));
+        complement
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(interval: Interval) -> Self {
+        IntervalSet {
+            intervals: vec![interval],
+        }
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = Interval>>(iter: T) -> Self {
+        let mut set = IntervalSet::new();
+        iter.into_iter().for_each(|interval| set.insert(interval));
+        set
+    }
+}
+
+impl Extend<Interval> for IntervalSet {
+    fn extend<T: IntoIterator<Item = Interval>>(&mut self, iter: T) {
+        iter.into_iter().for_each(|interval| self.insert(interval));
+    }
+}
+
+impl<'a> IntoIterator for &'a IntervalSet {
+    type Item = &'a Interval;
+    type IntoIter = std::slice::Iter<'a, Interval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for IntervalSet {
+    type Item = Interval;
+    type IntoIter = std::vec::IntoIter<Interval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.intervals.into_iter()
+    }
+}
+
+impl Debug for IntervalSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.intervals.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
+
+    fn set(intervals: &[(u64, u64)]) -> IntervalSet {
+        intervals
+            .iter()
+            .map(|(start, end)| Interval::inclusive(*start, *end).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_interval_constructors() {
+        assert_eq!(Interval::inclusive(3, 5), Some(Interval { start: 3, end: 5 }));
+        assert_eq!(Interval::inclusive(5, 3), None);
+        assert_eq!(Interval::half_open(3, 5), Some(Interval { start: 3, end: 4 }));
+        assert_eq!(Interval::half_open(3, 3), None);
+        assert_eq!(Interval::with_length(3, 2), Some(Interval { start: 3, end: 4 }));
+        assert_eq!(Interval::with_length(3, 0), None);
+        assert_eq!(Interval::with_length(u64::MAX, 2), None);
+        assert_eq!(Interval::inclusive(0, u64::MAX).unwrap().size(), 1 << 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "empty interval")]
+    #[cfg(debug_assertions)]
+    fn test_insert_rejects_empty_interval() {
+        IntervalSet::new().insert(Interval { start: 5, end: 4 });
+    }
+
+    #[test]
+    fn test_insert_coalesces() {
+        let mut intervals = set(&[(10, 20), (30, 40)]);
+        intervals.insert(Interval { start: 21, end: 29 });
+        assert_eq!(intervals, set(&[(10, 40)]));
+
+        intervals.insert(Interval { start: 50, end: 60 });
+        intervals.insert(Interval { start: 0, end: 5 });
+        assert_eq!(intervals.iter().count(), 3);
+        assert_eq!(intervals.min(), Some(0));
+        assert_eq!(intervals.max(), Some(60));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = set(&[(0, 10), (20, 30)]);
+        let b = set(&[(5, 25)]);
+
+        assert_eq!(a.union(&b), set(&[(0, 30)]));
+        assert_eq!(a.intersection(&b), set(&[(5, 10), (20, 25)]));
+        assert_eq!(a.difference(&b), set(&[(0, 4), (26, 30)]));
+        assert_eq!(
+            a.complement(&Interval { start: 0, end: 40 }),
+            set(&[(11, 19), (31, 40)])
+        );
+    }
+
+    #[test]
+    fn test_bounds_of_u64() {
+        let all = set(&[(0, u64::MAX)]);
+        assert!(all.contains(&u64::MAX));
+        assert!(all.complement(&Interval { start: 0, end: u64::MAX }).is_empty());
+
+        let mut ends = set(&[(0, 0), (u64::MAX, u64::MAX)]);
+        ends.remove(&Interval { start: 0, end: u64::MAX });
+        assert!(ends.is_empty());
+    }
+
+    // small domain, so that sets can be checked against a BTreeSet of their numbers
+    const DOMAIN: u64 = 64;
+
+    fn interval_strategy() -> impl Strategy<Value = Interval> {
+        (0..DOMAIN, 0..DOMAIN).prop_map(|(a, b)| Interval {
+            start: a.min(b),
+            end: a.max(b),
+        })
+    }
+
+    fn set_strategy() -> impl Strategy<Value = IntervalSet> {
+        prop::collection::vec(interval_strategy(), 0..8).prop_map(IntervalSet::from_iter)
+    }
+
+    fn numbers(set: &IntervalSet) -> BTreeSet<u64> {
+        set.iter().flat_map(|interval| interval.start..=interval.end).collect()
+    }
+
+    // sorted, disjoint and never adjacent
+    fn is_normalized(set: &IntervalSet) -> bool {
+        set.intervals
+            .windows(2)
+            .all(|pair| pair[0].end.saturating_add(1) < pair[1].start)
+            && set.iter().all(|interval| interval.start <= interval.end)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_insert_matches_model(intervals in prop::collection::vec(interval_strategy(), 0..8)) {
+            let set = intervals.iter().cloned().collect::<IntervalSet>();
+            let model = intervals
+                .iter()
+                .flat_map(|interval| interval.start..=interval.end)
+                .collect::<BTreeSet<_>>();
+
+            prop_assert!(is_normalized(&set));
+            prop_assert_eq!(numbers(&set), model);
+        }
+
+        #[test]
+        fn prop_set_operations_match_model(a in set_strategy(), b in set_strategy()) {
+            let (model_a, model_b) = (numbers(&a), numbers(&b));
+
+            let union = a.union(&b);
+            let intersection = a.intersection(&b);
+            let difference = a.difference(&b);
+
+            prop_assert!(is_normalized(&union));
+            prop_assert!(is_normalized(&intersection));
+            prop_assert!(is_normalized(&difference));
+            prop_assert_eq!(numbers(&union), &model_a | &model_b);
+            prop_assert_eq!(numbers(&intersection), &model_a & &model_b);
+            prop_assert_eq!(numbers(&difference), &model_a - &model_b);
+        }
+
+        #[test]
+        fn prop_complement_matches_model(a in set_strategy(), bound in interval_strategy()) {
+            let complement = a.complement(&bound);
+            let model = (bound.start..=bound.end)
+                .filter(|n| !numbers(&a).contains(n))
+                .collect::<BTreeSet<_>>();
+
+            prop_assert!(is_normalized(&complement));
+            prop_assert_eq!(numbers(&complement), model);
+        }
+
+        #[test]
+        fn prop_contains_and_len_match_model(a in set_strategy(), number in 0..DOMAIN) {
+            let model = numbers(&a);
+            prop_assert_eq!(a.contains(&number), model.contains(&number));
+            prop_assert_eq!(a.len(), model.len() as u128);
+        }
+
+        #[test]
+        fn prop_interval_difference_matches_model(a in interval_strategy(), b in interval_strategy()) {
+            let difference = a.difference(&b).into_iter().collect::<IntervalSet>();
+            let model = (a.start..=a.end)
+                .filter(|n| !b.contains(n))
+                .collect::<BTreeSet<_>>();
+
+            prop_assert_eq!(numbers(&difference), model);
+        }
+    }
+}